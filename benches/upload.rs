@@ -0,0 +1,72 @@
+// upload throughput against the mock twitter, for a few chunk sizes and
+// parallelism levels. the mock takes a bit to answer every request, standing
+// in for the round trip to twitter, which is what the parallel APPENDs hide.
+//
+//     cargo bench --features mock
+
+use fox_friday_bot::mock::twitter::MockTwitter;
+use fox_friday_bot::twbot::{Bot, RetryPolicy};
+use std::time::{Duration, Instant};
+
+const VIDEO_LEN: usize = 8 * 1024 * 1024;
+const LATENCY: Duration = Duration::from_millis(25);
+const RUNS: u32 = 2;
+
+// (chunk size, parallelism)
+const CASES: &[(usize, usize)] = &[
+    (1024 * 1000, 1),
+    (1024 * 1000, 2),
+    (1024 * 1000, 4),
+    (1024 * 1000, 8),
+    (4 * 1024 * 1024, 4),
+];
+
+fn bot(twitter: &MockTwitter, chunk_size: usize, parallelism: usize) -> Bot {
+    Bot::new(
+        "app-key".to_string(),
+        "app-secret".to_string(),
+        "user-token".to_string(),
+        "user-secret".to_string(),
+    )
+    .unwrap()
+    .with_api_url(&twitter.url())
+    .with_upload_url(&twitter.url())
+    .with_retry_policy(RetryPolicy::none())
+    .with_chunk_size(chunk_size)
+    .with_upload_parallelism(parallelism)
+}
+
+#[tokio::main]
+async fn main() {
+    let video: Vec<u8> = (0..VIDEO_LEN).map(|i| (i % 251) as u8).collect();
+    let twitter = MockTwitter::start().with_latency(LATENCY);
+
+    println!(
+        "{} KiB, {:?} a request, best of {}",
+        VIDEO_LEN / 1024,
+        LATENCY,
+        RUNS
+    );
+    for &(chunk_size, parallelism) in CASES {
+        let bot = bot(&twitter, chunk_size, parallelism);
+
+        let mut best = Duration::MAX;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            bot.upload_media(video.as_slice(), video.len())
+                .await
+                .unwrap();
+            best = best.min(start.elapsed());
+        }
+
+        // the one second pause after INIT isn't the upload's fault
+        let uploading = best.saturating_sub(Duration::from_secs(1));
+        println!(
+            "chunk {:>5} KiB, parallelism {}: {:>8.2?} ({:.1} MiB/s)",
+            chunk_size / 1024,
+            parallelism,
+            best,
+            VIDEO_LEN as f64 / (1024.0 * 1024.0) / uploading.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,78 @@
+// walks a new bot account through the PIN-based three-legged flow, so
+// nobody has to dig TWUSER_TOKEN/TWUSER_SECRET out of the developer portal.
+// `authorize oauth2` does the OAuth 2.0 PKCE flow instead, saving the tokens
+// to TWOAUTH2_TOKEN_FILE.
+
+use fox_friday_bot::credentials::{self, CredentialProvider};
+use fox_friday_bot::oauth::client::{Callback, OAuthClient};
+use fox_friday_bot::oauth::pkce::{self, FileTokenStore, PkceClient};
+use reqwest::blocking::Client;
+use std::env;
+use std::io::{self, BufRead, Write};
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+fn main() -> Result<(), SyncError> {
+    match env::args().nth(1).as_deref() {
+        Some("oauth2") => authorize_oauth2(),
+        _ => authorize_pin(),
+    }
+}
+
+fn authorize_oauth2() -> Result<(), SyncError> {
+    let credentials = credentials::from_env()?;
+    let client_id = credentials.require(credentials::OAUTH2_CLIENT_ID)?;
+    let token_file = credentials.require(credentials::OAUTH2_TOKEN_FILE)?;
+    let redirect_url = credentials
+        .get(credentials::OAUTH2_REDIRECT_URL)?
+        .unwrap_or_else(|| pkce::DEFAULT_REDIRECT_URL.to_string());
+
+    let mut oauth = PkceClient::new(client_id, redirect_url.clone())
+        .with_store(FileTokenStore::new(&token_file));
+    if let Some(secret) = credentials.get(credentials::OAUTH2_CLIENT_SECRET)? {
+        oauth = oauth.with_client_secret(secret);
+    }
+
+    oauth.authorize(|url| {
+        println!(
+            "Log in as the bot account and authorize the app here:\n\n    {}\n",
+            url
+        );
+        println!("Waiting for the redirect to {}...", redirect_url);
+    })?;
+    println!("Saved tokens to {}", token_file);
+
+    Ok(())
+}
+
+fn authorize_pin() -> Result<(), SyncError> {
+    let credentials = credentials::from_env()?;
+    let app_key = credentials.require(credentials::APP_KEY)?;
+    let app_secret = credentials.require(credentials::APP_SECRET)?;
+
+    let oauth = OAuthClient::new_consumer(app_key, app_secret);
+    let http = Client::builder()
+        .user_agent("OAuth fox-friday-bot")
+        .build()?;
+
+    let request_token = oauth.request_token(&http, Callback::OutOfBand)?;
+    println!(
+        "Log in as the bot account and authorize the app here:\n\n    {}\n",
+        oauth.authorize_url(&request_token)?
+    );
+
+    print!("PIN: ");
+    io::stdout().flush()?;
+    let mut pin = String::new();
+    io::stdin().lock().read_line(&mut pin)?;
+
+    let access_token = oauth.access_token(&http, &request_token, pin.trim())?;
+
+    if let Some(screen_name) = access_token.screen_name.as_ref() {
+        println!("\nAuthorized as @{}", screen_name);
+    }
+    println!("TWUSER_TOKEN={}", access_token.token);
+    println!("TWUSER_SECRET={}", access_token.secret);
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// manages the encrypted keyring at TWKEYRING_FILE, unlocked with
+// TWKEYRING_KEY_FILE or TWKEYRING_PASSPHRASE:
+//
+//     keyring gen-key <path>
+//     keyring list
+//     keyring add <name>
+//     keyring rotate <name>
+//     keyring remove <name>
+
+use fox_friday_bot::credentials::keyring::{self, CredentialSet, Keyring, Unlock};
+use fox_friday_bot::credentials::{self, CredentialProvider, EnvProvider};
+use reqwest::blocking::Client;
+use std::env;
+use std::io::{self, BufRead, Write};
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+const USAGE: &str =
+    "usage: keyring gen-key <path> | list | add <name> | rotate <name> | remove <name>";
+
+fn main() -> Result<(), SyncError> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    if let ["gen-key", path] = args.as_slice() {
+        keyring::generate_key_file(path)?;
+        println!("Wrote a new key to {}", path);
+        return Ok(());
+    }
+
+    let path = EnvProvider.require(credentials::KEYRING_FILE)?;
+    let mut keyring = Keyring::open(&path, &Unlock::from_env()?)?;
+
+    match args.as_slice() {
+        ["list"] => {
+            for name in keyring.names() {
+                println!("{}", name);
+            }
+        }
+        ["add", name] => {
+            keyring.add(name, prompt_set(None)?)?;
+            keyring.save()?;
+            println!("Added {}", name);
+        }
+        ["rotate", name] => {
+            let current = keyring.get(name)?.clone();
+            let http = Client::builder()
+                .user_agent("OAuth fox-friday-bot")
+                .build()?;
+
+            keyring.rotate(&http, name, prompt_set(Some(&current))?)?;
+            println!("Rotated {}", name);
+        }
+        ["remove", name] => {
+            keyring.remove(name)?;
+            keyring.save()?;
+            println!("Removed {}", name);
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
+
+// asks for each credential, keeping the current one on an empty line
+fn prompt_set(current: Option<&CredentialSet>) -> Result<CredentialSet, SyncError> {
+    let mut values = vec![];
+    for name in [
+        credentials::APP_KEY,
+        credentials::APP_SECRET,
+        credentials::USER_TOKEN,
+        credentials::USER_SECRET,
+    ]
+    .iter()
+    {
+        let keep = match current {
+            Some(set) => set.get(name)?,
+            None => None,
+        };
+
+        let value = loop {
+            match keep {
+                Some(_) => print!("{} (enter to keep): ", name),
+                None => print!("{}: ", name),
+            }
+            io::stdout().flush()?;
+
+            let mut line = String::new();
+            // input ran out, asking again won't help
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("no value given for {}", name),
+                )));
+            }
+            match (line.trim(), keep.as_ref()) {
+                ("", Some(keep)) => break keep.clone(),
+                ("", None) => continue,
+                (value, _) => break value.to_string(),
+            }
+        };
+        values.push(value);
+    }
+
+    let mut values = values.into_iter();
+    Ok(CredentialSet {
+        app_key: values.next().unwrap(),
+        app_secret: values.next().unwrap(),
+        user_token: values.next().unwrap(),
+        user_secret: values.next().unwrap(),
+    })
+}
@@ -0,0 +1,56 @@
+use super::{CredentialError, CredentialProvider, SyncError};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// One file per credential, like Docker and Kubernetes secrets mount them
+/// (`/run/secrets/twapp_key`). The file can be named after the credential
+/// or its lowercase, and a trailing newline is ignored.
+pub struct DirectoryProvider {
+    dir: PathBuf,
+}
+
+impl DirectoryProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DirectoryProvider { dir: dir.into() }
+    }
+}
+
+impl CredentialProvider for DirectoryProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+        for file in [name.to_string(), name.to_ascii_lowercase()].iter() {
+            match fs::read(self.dir.join(file)) {
+                Ok(bytes) => {
+                    let value = String::from_utf8(bytes).map_err(|_| {
+                        CredentialError::Invalid(name.to_string(), "not valid UTF-8".to_string())
+                    })?;
+                    return Ok(Some(value.trim_end_matches(&['\r', '\n'][..]).to_string()));
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_provider() {
+        let dir = std::env::temp_dir().join(format!("fox-friday-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("TWAPP_KEY"), "key\n").unwrap();
+        fs::write(dir.join("twapp_secret"), "secret").unwrap();
+
+        let provider = DirectoryProvider::new(&dir);
+        assert_eq!(provider.require("TWAPP_KEY").unwrap(), "key");
+        assert_eq!(provider.require("TWAPP_SECRET").unwrap(), "secret");
+        assert_eq!(provider.get("TWUSER_TOKEN").unwrap(), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
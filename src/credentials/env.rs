@@ -0,0 +1,36 @@
+use super::{CredentialError, CredentialProvider, SyncError};
+use std::env::{self, VarError};
+
+/// Credentials straight out of the environment, which is how the bot's
+/// always been configured.
+pub struct EnvProvider;
+
+impl CredentialProvider for EnvProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+        match env::var(name) {
+            Ok(value) => Ok(Some(value)),
+            Err(VarError::NotPresent) => Ok(None),
+            Err(VarError::NotUnicode(_)) => Err(Box::new(CredentialError::Invalid(
+                name.to_string(),
+                "not valid unicode".to_string(),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_provider() {
+        // nothing else uses these, so setting them won't race other tests
+        env::set_var("TWTEST_ENV_PROVIDER", "value");
+
+        assert_eq!(
+            EnvProvider.get("TWTEST_ENV_PROVIDER").unwrap().as_deref(),
+            Some("value")
+        );
+        assert_eq!(EnvProvider.get("TWTEST_ENV_PROVIDER_UNSET").unwrap(), None);
+    }
+}
@@ -0,0 +1,102 @@
+use super::{CredentialError, CredentialProvider, SyncError};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A flat table of credentials in a TOML file, or JSON if the file ends in
+/// `.json`:
+///
+/// ```toml
+/// TWAPP_KEY = "..."
+/// TWAPP_SECRET = "..."
+/// ```
+pub struct FileProvider {
+    values: HashMap<String, String>,
+}
+
+impl FileProvider {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SyncError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        let is_json = path.extension().and_then(|e| e.to_str()) == Some("json");
+        let parsed = if is_json {
+            Self::from_json(&contents)
+        } else {
+            Self::from_toml(&contents)
+        };
+
+        // say which file it was, the parsers don't know
+        parsed.map_err(|e| {
+            Box::new(CredentialError::Invalid(
+                path.display().to_string(),
+                e.to_string(),
+            )) as SyncError
+        })
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, SyncError> {
+        Ok(FileProvider {
+            values: toml::from_str(contents)?,
+        })
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, SyncError> {
+        Ok(FileProvider {
+            values: serde_json::from_str(contents)?,
+        })
+    }
+}
+
+impl CredentialProvider for FileProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+        Ok(self.values.get(name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_and_json() {
+        let toml =
+            FileProvider::from_toml("TWAPP_KEY = \"key\"\nTWAPP_SECRET = \"secret\"\n").unwrap();
+        let json =
+            FileProvider::from_json(r#"{"TWAPP_KEY": "key", "TWAPP_SECRET": "secret"}"#).unwrap();
+
+        for provider in [toml, json].iter() {
+            assert_eq!(provider.require("TWAPP_KEY").unwrap(), "key");
+            assert_eq!(provider.require("TWAPP_SECRET").unwrap(), "secret");
+            assert_eq!(provider.get("TWUSER_TOKEN").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_open_by_extension() {
+        let dir = std::env::temp_dir();
+        let json = dir.join(format!(
+            "fox-friday-credentials-{}.json",
+            std::process::id()
+        ));
+        let toml = dir.join(format!(
+            "fox-friday-credentials-{}.toml",
+            std::process::id()
+        ));
+        fs::write(&json, r#"{"TWAPP_KEY": "key"}"#).unwrap();
+        fs::write(&toml, "TWAPP_KEY = [\"not\", \"a\", \"string\"]").unwrap();
+
+        assert_eq!(
+            FileProvider::open(&json)
+                .unwrap()
+                .require("TWAPP_KEY")
+                .unwrap(),
+            "key"
+        );
+        let err = FileProvider::open(&toml).err().unwrap();
+        assert!(err.to_string().contains(&toml.display().to_string()));
+
+        fs::remove_file(json).unwrap();
+        fs::remove_file(toml).unwrap();
+    }
+}
@@ -0,0 +1,585 @@
+// several named sets of the bot's credentials in one file, encrypted with
+// AES-256-GCM under a key from a passphrase (scrypt) or a key file, so the
+// secrets can sit on disk on every machine that runs the bot.
+
+use super::{CredentialProvider, EnvProvider, SyncError};
+use crate::oauth::base64::{self, STANDARD};
+use crate::oauth::client::OAuthClient;
+use crate::oauth::echo::VERIFY_CREDENTIALS_URL;
+use crate::oauth::redact;
+use crate::oauth::Authenticator;
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce, Tag};
+use log::info;
+use rand::RngCore;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+const FORMAT_VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+
+// scrypt's recommended interactive cost. the tests don't need to wait on it,
+// and since the cost is saved in the file either one can open the other's
+#[cfg(not(test))]
+const SCRYPT_LOG_N: u8 = 15;
+#[cfg(test)]
+const SCRYPT_LOG_N: u8 = 10;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+// the costs a keyring file's allowed to ask for. the file says what they
+// are, so without these a broken (or hostile) one could have us grinding
+// through scrypt for ages before the key's even checked
+const SCRYPT_LOG_N_BOUNDS: RangeInclusive<u8> = 10..=20;
+const SCRYPT_R_BOUNDS: RangeInclusive<u32> = 1..=16;
+const SCRYPT_P_BOUNDS: RangeInclusive<u32> = 1..=16;
+
+/// The four secrets the bot signs with.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct CredentialSet {
+    pub app_key: String,
+    pub app_secret: String,
+    pub user_token: String,
+    pub user_secret: String,
+}
+
+impl fmt::Debug for CredentialSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialSet")
+            .field("app_key", &redact::MASK)
+            .field("app_secret", &redact::MASK)
+            .field("user_token", &redact::MASK)
+            .field("user_secret", &redact::MASK)
+            .finish()
+    }
+}
+
+impl CredentialProvider for CredentialSet {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+        Ok(match name {
+            super::APP_KEY => Some(self.app_key.clone()),
+            super::APP_SECRET => Some(self.app_secret.clone()),
+            super::USER_TOKEN => Some(self.user_token.clone()),
+            super::USER_SECRET => Some(self.user_secret.clone()),
+            _ => None,
+        })
+    }
+}
+
+/// What unlocks the keyring.
+pub enum Unlock {
+    Passphrase(String),
+    /// A file holding a base64'd 256-bit key, as made by `generate_key_file`.
+    KeyFile(PathBuf),
+}
+
+impl Unlock {
+    /// TWKEYRING_KEY_FILE if it's set, TWKEYRING_PASSPHRASE otherwise.
+    pub fn from_env() -> Result<Self, SyncError> {
+        if let Some(path) = EnvProvider.get(super::KEYRING_KEY_FILE)? {
+            return Ok(Unlock::KeyFile(path.into()));
+        }
+
+        Ok(Unlock::Passphrase(
+            EnvProvider.require(super::KEYRING_PASSPHRASE)?,
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyringError {
+    /// Wrong passphrase or key, or the file's been tampered with.
+    BadKey,
+    /// A passphrase keyring opened with a key file, or the other way around.
+    WrongUnlock,
+    InvalidKeyFile(String),
+    /// The file's scrypt parameters are outside what we'll run.
+    InvalidKdf(String),
+    UnsupportedVersion(u32),
+    NotFound(String),
+    AlreadyExists(String),
+    /// verify_credentials turned down the new credentials.
+    Rejected(u16, String),
+}
+
+impl fmt::Display for KeyringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyringError::BadKey => write!(f, "could not decrypt keyring, wrong key?"),
+            KeyringError::WrongUnlock => {
+                write!(f, "keyring is locked with a different kind of key")
+            }
+            KeyringError::InvalidKeyFile(reason) => write!(f, "invalid key file: {}", reason),
+            KeyringError::InvalidKdf(reason) => {
+                write!(f, "invalid keyring scrypt parameters: {}", reason)
+            }
+            KeyringError::UnsupportedVersion(v) => {
+                write!(f, "unsupported keyring version {}", v)
+            }
+            KeyringError::NotFound(name) => write!(f, "no credentials named {}", name),
+            KeyringError::AlreadyExists(name) => {
+                write!(f, "credentials named {} already exist", name)
+            }
+            KeyringError::Rejected(code, body) => {
+                write!(f, "verify_credentials returned {}: {}", code, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyringError {}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Kdf {
+    salt: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+// what's actually on disk. everything but the version and kdf is base64
+#[derive(Serialize, Deserialize)]
+struct KeyringFile {
+    version: u32,
+    kdf: Option<Kdf>,
+    nonce: String,
+    tag: String,
+    ciphertext: String,
+}
+
+pub struct Keyring {
+    path: PathBuf,
+    key: [u8; KEY_LEN],
+    // None for key files
+    kdf: Option<Kdf>,
+    sets: BTreeMap<String, CredentialSet>,
+    verify_url: String,
+}
+
+impl Keyring {
+    /// Opens the keyring at `path`, or starts an empty one if there's
+    /// nothing there yet. Nothing's written until `save`.
+    pub fn open(path: impl Into<PathBuf>, unlock: &Unlock) -> Result<Self, SyncError> {
+        let path = path.into();
+        let file: Option<KeyringFile> = match fs::read(&path) {
+            Ok(bytes) => Some(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let file = match file {
+            Some(file) => file,
+            None => {
+                let kdf = match unlock {
+                    Unlock::Passphrase(_) => Some(Kdf {
+                        salt: base64::bytes_to_base64(random_bytes(SALT_LEN)),
+                        log_n: SCRYPT_LOG_N,
+                        r: SCRYPT_R,
+                        p: SCRYPT_P,
+                    }),
+                    Unlock::KeyFile(_) => None,
+                };
+
+                return Ok(Keyring {
+                    key: derive_key(unlock, kdf.as_ref())?,
+                    path,
+                    kdf,
+                    sets: BTreeMap::new(),
+                    verify_url: VERIFY_CREDENTIALS_URL.to_string(),
+                });
+            }
+        };
+
+        if file.version != FORMAT_VERSION {
+            return Err(Box::new(KeyringError::UnsupportedVersion(file.version)));
+        }
+        let key = derive_key(unlock, file.kdf.as_ref())?;
+
+        let nonce = base64::decode(&file.nonce, STANDARD)?;
+        let tag = base64::decode(&file.tag, STANDARD)?;
+        let ciphertext = base64::decode(&file.ciphertext, STANDARD)?;
+        if nonce.len() != NONCE_LEN || tag.len() != TAG_LEN {
+            return Err(Box::new(KeyringError::BadKey));
+        }
+
+        let mut plaintext = ciphertext;
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&nonce),
+                &aad(file.version),
+                &mut plaintext,
+                Tag::from_slice(&tag),
+            )
+            .map_err(|_| KeyringError::BadKey)?;
+
+        Ok(Keyring {
+            path,
+            key,
+            kdf: file.kdf,
+            sets: serde_json::from_slice(&plaintext)?,
+            verify_url: VERIFY_CREDENTIALS_URL.to_string(),
+        })
+    }
+
+    /// Checks rotated credentials somewhere other than Twitter.
+    pub fn with_verify_url(mut self, url: &str) -> Self {
+        self.verify_url = url.to_string();
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sets.keys().map(|k| k.as_str())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&CredentialSet, KeyringError> {
+        self.sets
+            .get(name)
+            .ok_or_else(|| KeyringError::NotFound(name.to_string()))
+    }
+
+    pub fn add(&mut self, name: &str, credentials: CredentialSet) -> Result<(), KeyringError> {
+        if self.sets.contains_key(name) {
+            return Err(KeyringError::AlreadyExists(name.to_string()));
+        }
+
+        self.sets.insert(name.to_string(), credentials);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<CredentialSet, KeyringError> {
+        self.sets
+            .remove(name)
+            .ok_or_else(|| KeyringError::NotFound(name.to_string()))
+    }
+
+    /// Replaces a set with new credentials and saves, but only once
+    /// verify_credentials has accepted them; until then the old ones stay.
+    pub fn rotate(
+        &mut self,
+        http: &Client,
+        name: &str,
+        credentials: CredentialSet,
+    ) -> Result<(), SyncError> {
+        self.get(name)?;
+        self.verify(http, &credentials)?;
+
+        let old = self.sets.insert(name.to_string(), credentials);
+        if let Err(e) = self.save() {
+            // don't leave the unsaved ones in memory either
+            self.sets.insert(name.to_string(), old.unwrap());
+            return Err(e);
+        }
+        info!("Rotated credentials {}", name);
+
+        Ok(())
+    }
+
+    fn verify(&self, http: &Client, credentials: &CredentialSet) -> Result<(), SyncError> {
+        let oauth = OAuthClient::new(
+            credentials.app_key.clone(),
+            credentials.app_secret.clone(),
+            credentials.user_token.clone(),
+            credentials.user_secret.clone(),
+        );
+        let request = http.get(&self.verify_url).build()?;
+        let response = oauth.execute(http, request)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Box::new(KeyringError::Rejected(
+                status.as_u16(),
+                response.text()?,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts everything under a fresh nonce and writes it out, only
+    /// readable by us.
+    pub fn save(&self) -> Result<(), SyncError> {
+        let mut ciphertext = serde_json::to_vec(&self.sets)?;
+        let nonce = random_bytes(NONCE_LEN);
+
+        let tag = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+            .encrypt_in_place_detached(
+                Nonce::from_slice(&nonce),
+                &aad(FORMAT_VERSION),
+                &mut ciphertext,
+            )
+            .map_err(|_| "keyring too big to encrypt")?;
+
+        let file = KeyringFile {
+            version: FORMAT_VERSION,
+            kdf: self.kdf.clone(),
+            nonce: base64::bytes_to_base64(nonce),
+            tag: base64::bytes_to_base64(tag),
+            ciphertext: base64::bytes_to_base64(ciphertext),
+        };
+        write_private(&self.path, &serde_json::to_vec_pretty(&file)?)
+    }
+}
+
+/// Makes a new random key for `Unlock::KeyFile`.
+pub fn generate_key_file(path: impl AsRef<Path>) -> Result<(), SyncError> {
+    let key = base64::bytes_to_base64(random_bytes(KEY_LEN));
+    write_private(path.as_ref(), format!("{}\n", key).as_bytes())
+}
+
+fn derive_key(unlock: &Unlock, kdf: Option<&Kdf>) -> Result<[u8; KEY_LEN], SyncError> {
+    let mut key = [0; KEY_LEN];
+
+    match (unlock, kdf) {
+        (Unlock::Passphrase(passphrase), Some(kdf)) => {
+            let salt = base64::decode(&kdf.salt, STANDARD)?;
+            scrypt::scrypt(passphrase.as_bytes(), &salt, &scrypt_params(kdf)?, &mut key)
+                .map_err(|e| KeyringError::InvalidKdf(e.to_string()))?;
+        }
+        (Unlock::KeyFile(path), None) => {
+            let contents = fs::read_to_string(path)?;
+            let bytes = base64::decode(contents.trim(), STANDARD)
+                .map_err(|e| KeyringError::InvalidKeyFile(e.to_string()))?;
+            if bytes.len() != KEY_LEN {
+                return Err(Box::new(KeyringError::InvalidKeyFile(format!(
+                    "expected {} bytes, got {}",
+                    KEY_LEN,
+                    bytes.len()
+                ))));
+            }
+            key.copy_from_slice(&bytes);
+        }
+        _ => return Err(Box::new(KeyringError::WrongUnlock)),
+    }
+
+    Ok(key)
+}
+
+fn scrypt_params(kdf: &Kdf) -> Result<scrypt::Params, KeyringError> {
+    if !SCRYPT_LOG_N_BOUNDS.contains(&kdf.log_n) {
+        return Err(KeyringError::InvalidKdf(format!(
+            "log_n {} isn't in {:?}",
+            kdf.log_n, SCRYPT_LOG_N_BOUNDS
+        )));
+    }
+    if !SCRYPT_R_BOUNDS.contains(&kdf.r) {
+        return Err(KeyringError::InvalidKdf(format!(
+            "r {} isn't in {:?}",
+            kdf.r, SCRYPT_R_BOUNDS
+        )));
+    }
+    if !SCRYPT_P_BOUNDS.contains(&kdf.p) {
+        return Err(KeyringError::InvalidKdf(format!(
+            "p {} isn't in {:?}",
+            kdf.p, SCRYPT_P_BOUNDS
+        )));
+    }
+
+    scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, KEY_LEN)
+        .map_err(|e| KeyringError::InvalidKdf(e.to_string()))
+}
+
+// ties the ciphertext to the format it was written in
+fn aad(version: u32) -> Vec<u8> {
+    format!("fox-friday-keyring-v{}", version).into_bytes()
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+// same as FileTokenStore: write next to it and rename, owner-only
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), SyncError> {
+    let tmp = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fox-friday-{}-{}", name, std::process::id()))
+    }
+
+    fn set(n: u32) -> CredentialSet {
+        CredentialSet {
+            app_key: "app-key".to_string(),
+            app_secret: "app-secret".to_string(),
+            user_token: format!("user-token-{}", n),
+            user_secret: format!("user-secret-{}", n),
+        }
+    }
+
+    #[test]
+    fn test_passphrase_round_trip() {
+        let path = temp_path("keyring-passphrase.json");
+        let unlock = Unlock::Passphrase("correct horse".to_string());
+
+        let mut keyring = Keyring::open(&path, &unlock).unwrap();
+        keyring.add("prod", set(1)).unwrap();
+        keyring.add("staging", set(2)).unwrap();
+        assert!(keyring.add("prod", set(3)).is_err());
+        keyring.save().unwrap();
+
+        // nothing readable on disk
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("user-token"));
+
+        let mut keyring = Keyring::open(&path, &unlock).unwrap();
+        assert_eq!(keyring.names().collect::<Vec<_>>(), vec!["prod", "staging"]);
+        assert_eq!(keyring.get("prod").unwrap(), &set(1));
+        assert_eq!(
+            keyring
+                .get("staging")
+                .unwrap()
+                .require(super::super::USER_TOKEN)
+                .unwrap(),
+            "user-token-2"
+        );
+
+        keyring.remove("staging").unwrap();
+        keyring.save().unwrap();
+        let keyring = Keyring::open(&path, &unlock).unwrap();
+        assert!(matches!(
+            keyring.get("staging"),
+            Err(KeyringError::NotFound(_))
+        ));
+
+        let err = Keyring::open(&path, &Unlock::Passphrase("wrong".to_string()))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<KeyringError>(),
+            Some(KeyringError::BadKey)
+        ));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_key_file() {
+        let path = temp_path("keyring-keyfile.json");
+        let key = temp_path("keyring.key");
+        generate_key_file(&key).unwrap();
+        let unlock = Unlock::KeyFile(key.clone());
+
+        let mut keyring = Keyring::open(&path, &unlock).unwrap();
+        keyring.add("prod", set(1)).unwrap();
+        keyring.save().unwrap();
+
+        let keyring = Keyring::open(&path, &unlock).unwrap();
+        assert_eq!(keyring.get("prod").unwrap(), &set(1));
+
+        // a different key, or a passphrase, doesn't open it
+        generate_key_file(&key).unwrap();
+        assert!(Keyring::open(&path, &unlock).is_err());
+        let err = Keyring::open(&path, &Unlock::Passphrase("key".to_string()))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<KeyringError>(),
+            Some(KeyringError::WrongUnlock)
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [&path, &key].iter() {
+                let mode = fs::metadata(file).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+        }
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(key).unwrap();
+    }
+
+    #[test]
+    fn test_kdf_bounds() {
+        let path = temp_path("keyring-kdf.json");
+        let unlock = Unlock::Passphrase("correct horse".to_string());
+        Keyring::open(&path, &unlock).unwrap().save().unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+
+        let out_of_bounds = [("log_n", 40), ("log_n", 4), ("r", 0), ("p", 0), ("p", 1000)];
+        for (param, value) in out_of_bounds.iter() {
+            let mut file = saved.clone();
+            file["kdf"][*param] = (*value).into();
+            fs::write(&path, file.to_string()).unwrap();
+
+            let err = Keyring::open(&path, &unlock).err().unwrap();
+            assert!(matches!(
+                err.downcast_ref::<KeyringError>(),
+                Some(KeyringError::InvalidKdf(_))
+            ));
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_verifies_first() {
+        // only user-token-2 is any good
+        let server = MockServer::start(|request| {
+            let auth = request.header("authorization").unwrap_or("");
+            if auth.contains("oauth_token=\"user-token-2\"") {
+                MockResponse::new(200, r#"{"id_str":"1","screen_name":"foxfriday"}"#)
+            } else {
+                MockResponse::new(401, "Invalid or expired token")
+            }
+        });
+        let path = temp_path("keyring-rotate.json");
+        let unlock = Unlock::Passphrase("hunter2".to_string());
+        let http = Client::new();
+
+        let mut keyring = Keyring::open(&path, &unlock)
+            .unwrap()
+            .with_verify_url(&server.url("/1.1/account/verify_credentials.json"));
+        keyring.add("prod", set(1)).unwrap();
+        keyring.save().unwrap();
+
+        let err = keyring.rotate(&http, "prod", set(3)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyringError>(),
+            Some(KeyringError::Rejected(401, _))
+        ));
+        assert_eq!(keyring.get("prod").unwrap(), &set(1));
+
+        let err = keyring.rotate(&http, "dev", set(2)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<KeyringError>(),
+            Some(KeyringError::NotFound(_))
+        ));
+
+        keyring.rotate(&http, "prod", set(2)).unwrap();
+        let keyring = Keyring::open(&path, &unlock).unwrap();
+        assert_eq!(keyring.get("prod").unwrap(), &set(2));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+// where the bot's keys and tokens come from. everything's looked up by its
+// env var name (TWAPP_KEY and friends), whether it's actually in the
+// environment, a config file, a secrets directory or a secrets service.
+
+pub mod directory;
+pub mod env;
+pub mod file;
+pub mod keyring;
+pub mod secrets;
+
+pub use directory::DirectoryProvider;
+pub use env::EnvProvider;
+pub use file::FileProvider;
+pub use keyring::Keyring;
+pub use secrets::SecretsManagerProvider;
+
+use std::fmt;
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+pub const APP_KEY: &str = "TWAPP_KEY";
+pub const APP_SECRET: &str = "TWAPP_SECRET";
+pub const USER_TOKEN: &str = "TWUSER_TOKEN";
+pub const USER_SECRET: &str = "TWUSER_SECRET";
+pub const OAUTH2_CLIENT_ID: &str = "TWOAUTH2_CLIENT_ID";
+pub const OAUTH2_CLIENT_SECRET: &str = "TWOAUTH2_CLIENT_SECRET";
+pub const OAUTH2_TOKEN_FILE: &str = "TWOAUTH2_TOKEN_FILE";
+pub const OAUTH2_REDIRECT_URL: &str = "TWOAUTH2_REDIRECT_URL";
+
+/// Env vars that point `from_env` at the other providers.
+pub const CREDENTIALS_FILE: &str = "TWCREDENTIALS_FILE";
+pub const CREDENTIALS_DIR: &str = "TWCREDENTIALS_DIR";
+pub const SECRET_ID: &str = "TWSECRET_ID";
+pub const KEYRING_FILE: &str = "TWKEYRING_FILE";
+/// Which set in the keyring to use.
+pub const KEYRING_NAME: &str = "TWKEYRING_NAME";
+pub const KEYRING_PASSPHRASE: &str = "TWKEYRING_PASSPHRASE";
+pub const KEYRING_KEY_FILE: &str = "TWKEYRING_KEY_FILE";
+
+#[derive(Debug)]
+pub enum CredentialError {
+    /// No provider had it.
+    Missing(String),
+    /// It's there, but it isn't something we can use (e.g. not UTF-8).
+    Invalid(String, String),
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialError::Missing(name) => write!(f, "missing credential {}", name),
+            CredentialError::Invalid(name, reason) => {
+                write!(f, "invalid credential {}: {}", name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+pub trait CredentialProvider: Send + Sync {
+    /// The credential, or `None` if this provider doesn't have it. Errors are
+    /// for when the provider itself couldn't be read.
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError>;
+
+    /// `get`, but with a missing credential being an error that names it.
+    fn require(&self, name: &str) -> Result<String, SyncError> {
+        match self.get(name)? {
+            Some(value) => Ok(value),
+            None => Err(Box::new(CredentialError::Missing(name.to_string()))),
+        }
+    }
+}
+
+/// Asks each provider in turn, taking the first one that has it.
+#[derive(Default)]
+pub struct ProviderChain {
+    providers: Vec<Box<dyn CredentialProvider>>,
+}
+
+impl ProviderChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, provider: impl CredentialProvider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+}
+
+impl CredentialProvider for ProviderChain {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+        for provider in self.providers.iter() {
+            if let Some(value) = provider.get(name)? {
+                return Ok(Some(value));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// The environment first, then whichever of TWCREDENTIALS_FILE,
+/// TWCREDENTIALS_DIR, TWSECRET_ID and TWKEYRING_FILE are set, in that order.
+pub fn from_env() -> Result<ProviderChain, SyncError> {
+    let mut chain = ProviderChain::new().with(EnvProvider);
+
+    if let Some(path) = EnvProvider.get(CREDENTIALS_FILE)? {
+        chain = chain.with(FileProvider::open(path)?);
+    }
+    if let Some(dir) = EnvProvider.get(CREDENTIALS_DIR)? {
+        chain = chain.with(DirectoryProvider::new(dir));
+    }
+    if let Some(secret_id) = EnvProvider.get(SECRET_ID)? {
+        chain = chain.with(SecretsManagerProvider::from_lambda_env(&secret_id)?);
+    }
+    if let Some(path) = EnvProvider.get(KEYRING_FILE)? {
+        let keyring = Keyring::open(path, &keyring::Unlock::from_env()?)?;
+        chain = chain.with(keyring.get(&EnvProvider.require(KEYRING_NAME)?)?.clone());
+    }
+
+    Ok(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct MapProvider(HashMap<&'static str, &'static str>);
+
+    impl CredentialProvider for MapProvider {
+        fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+            Ok(self.0.get(name).map(|v| v.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_provider_chain() {
+        let chain = ProviderChain::new()
+            .with(MapProvider(
+                vec![(APP_KEY, "from-first")].into_iter().collect(),
+            ))
+            .with(MapProvider(
+                vec![(APP_KEY, "from-second"), (APP_SECRET, "secret")]
+                    .into_iter()
+                    .collect(),
+            ));
+
+        assert_eq!(chain.require(APP_KEY).unwrap(), "from-first");
+        assert_eq!(chain.require(APP_SECRET).unwrap(), "secret");
+        assert_eq!(chain.get(USER_TOKEN).unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_credential_is_named() {
+        let err = ProviderChain::new().require(USER_SECRET).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<CredentialError>(),
+            Some(CredentialError::Missing(name)) if name == USER_SECRET
+        ));
+        assert_eq!(err.to_string(), "missing credential TWUSER_SECRET");
+    }
+}
@@ -0,0 +1,185 @@
+// credentials kept as one JSON secret in AWS Secrets Manager, read through
+// the Parameters and Secrets Lambda extension's local HTTP endpoint, or
+// anything else that answers the same way.
+
+use super::{CredentialError, CredentialProvider, SyncError};
+use log::debug;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Where the extension listens unless PARAMETERS_SECRETS_EXTENSION_HTTP_PORT
+/// says otherwise.
+pub const DEFAULT_PORT: u16 = 2773;
+pub const TOKEN_HEADER: &str = "X-Aws-Parameters-Secrets-Token";
+
+#[derive(Deserialize)]
+struct SecretValue {
+    #[serde(rename = "SecretString")]
+    secret_string: String,
+}
+
+#[derive(Debug)]
+pub enum SecretsError {
+    Status(u16, String),
+}
+
+impl fmt::Display for SecretsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretsError::Status(code, body) => {
+                write!(f, "secrets endpoint returned {}: {}", code, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecretsError {}
+
+pub struct SecretsManagerProvider {
+    endpoint: String,
+    secret_id: String,
+    token: Option<String>,
+    http: Client,
+    // the whole secret, fetched the first time anything's asked for
+    values: Mutex<Option<HashMap<String, String>>>,
+}
+
+impl SecretsManagerProvider {
+    /// Reads `secret_id` from the extension-style endpoint at `endpoint`
+    /// (e.g. `http://localhost:2773`). The secret has to be a JSON object
+    /// of credential names to values.
+    pub fn new(endpoint: &str, secret_id: &str) -> Self {
+        SecretsManagerProvider {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            secret_id: secret_id.to_string(),
+            token: None,
+            http: Client::new(),
+            values: Mutex::new(None),
+        }
+    }
+
+    /// The extension on this Lambda, authenticated with its session token.
+    pub fn from_lambda_env(secret_id: &str) -> Result<Self, SyncError> {
+        let port = match env::var("PARAMETERS_SECRETS_EXTENSION_HTTP_PORT") {
+            Ok(port) => port.parse()?,
+            Err(_) => DEFAULT_PORT,
+        };
+
+        let mut provider = Self::new(&format!("http://localhost:{}", port), secret_id);
+        if let Ok(token) = env::var("AWS_SESSION_TOKEN") {
+            provider = provider.with_token(&token);
+        }
+
+        Ok(provider)
+    }
+
+    /// Sent as X-Aws-Parameters-Secrets-Token.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    fn fetch(&self) -> Result<HashMap<String, String>, SyncError> {
+        debug!("Fetching secret {} from {}", self.secret_id, self.endpoint);
+        let mut request = self
+            .http
+            .get(format!("{}/secretsmanager/get", self.endpoint))
+            .query(&[("secretId", self.secret_id.as_str())]);
+        if let Some(token) = self.token.as_ref() {
+            request = request.header(TOKEN_HEADER, token);
+        }
+
+        let response = request.send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Box::new(SecretsError::Status(
+                status.as_u16(),
+                response.text()?,
+            )));
+        }
+
+        let value: SecretValue = response.json()?;
+        serde_json::from_str(&value.secret_string).map_err(|e| {
+            Box::new(CredentialError::Invalid(
+                self.secret_id.clone(),
+                e.to_string(),
+            )) as SyncError
+        })
+    }
+}
+
+impl CredentialProvider for SecretsManagerProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, SyncError> {
+        let mut values = self.values.lock().unwrap();
+        if values.is_none() {
+            *values = Some(self.fetch()?);
+        }
+
+        Ok(values.as_ref().and_then(|v| v.get(name).cloned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    fn server() -> MockServer {
+        MockServer::start(|request| {
+            if request.header(TOKEN_HEADER) != Some("session-token") {
+                return MockResponse::new(401, "no token");
+            }
+
+            match request.path.as_str() {
+                "/secretsmanager/get?secretId=fox-friday%2Fprod" => MockResponse::new(
+                    200,
+                    serde_json::json!({
+                        "Name": "fox-friday/prod",
+                        "SecretString": r#"{"TWAPP_KEY":"key","TWAPP_SECRET":"secret"}"#,
+                    })
+                    .to_string(),
+                ),
+                _ => MockResponse::new(400, "secret not found"),
+            }
+        })
+    }
+
+    #[test]
+    fn test_secrets_manager_provider() {
+        let server = server();
+        let provider = SecretsManagerProvider::new(&server.url(""), "fox-friday/prod")
+            .with_token("session-token");
+
+        assert_eq!(provider.require("TWAPP_KEY").unwrap(), "key");
+        assert_eq!(provider.require("TWAPP_SECRET").unwrap(), "secret");
+        assert_eq!(provider.get("TWUSER_TOKEN").unwrap(), None);
+        // only fetched the once
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_secrets_manager_errors() {
+        let server = server();
+
+        let err = SecretsManagerProvider::new(&server.url(""), "fox-friday/prod")
+            .get("TWAPP_KEY")
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SecretsError>(),
+            Some(SecretsError::Status(401, _))
+        ));
+
+        let err = SecretsManagerProvider::new(&server.url(""), "fox-friday/dev")
+            .with_token("session-token")
+            .get("TWAPP_KEY")
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SecretsError>(),
+            Some(SecretsError::Status(400, _))
+        ));
+    }
+}
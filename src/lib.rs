@@ -0,0 +1,6 @@
+pub mod credentials;
+pub mod oauth;
+pub mod twbot;
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
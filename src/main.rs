@@ -1,8 +1,12 @@
+use fox_friday_bot::twbot::{self, BotError, UploadState, UploadStore};
 use lambda_runtime::{handler_fn, run, Context, Error};
 use simple_logger::SimpleLogger;
 
-mod oauth;
-mod twbot;
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+const BUCKET: &str = "fox-friday-bot-bucket";
+const FOX_VIDEO: &str = "/fox_friday.mp4";
+const UPLOAD_STATE: &str = "/upload_state.json";
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -28,31 +32,84 @@ struct Response {
 } // don't care
 
 async fn tweet_fox_video(_: Event, _: Context) -> Result<Response, Error> {
-    let bot = twbot::Bot::new_from_env()?;
-    let fox_video = s3::Bucket::new(
-        "fox-friday-bot-bucket",
-        s3::Region::UsWest1,
-        s3::creds::Credentials::from_env()?,
-    )?
-    .get_object("/fox_friday.mp4")
-    .await?;
+    let bucket = bucket()?;
+    // the credential providers block, keep them off the runtime
+    let bot = tokio::task::spawn_blocking(twbot::Bot::new_from_env)
+        .await??
+        .with_upload_store(S3UploadStore {
+            bucket: bucket.clone(),
+            path: UPLOAD_STATE,
+        });
+    let fox_video = fetch_fox_video(&bucket).await?;
 
     log::info!("Got fox video: code: {}, len: {}", fox_video.1, fox_video.0.len());
 
-    let media = bot.upload_media(fox_video.0.as_slice(), fox_video.0.len())?;
+    let media = bot
+        .upload_media(fox_video.0.as_slice(), fox_video.0.len())
+        .await?;
 
-    bot.tweet_status_with_media(String::from(""), vec![media])?;
+    bot.tweet_status_with_media(String::from(""), vec![media])
+        .await?;
 
     let resp = Response { msg: "OK" };
 
     Ok(resp)
 }
 
+fn bucket() -> Result<s3::Bucket, BotError> {
+    let credentials = s3::creds::Credentials::from_env().map_err(|e| BotError::S3(e.into()))?;
+    s3::Bucket::new(BUCKET, s3::Region::UsWest1, credentials).map_err(|e| BotError::S3(e.into()))
+}
+
+async fn fetch_fox_video(bucket: &s3::Bucket) -> Result<(Vec<u8>, u16), BotError> {
+    bucket
+        .get_object(FOX_VIDEO)
+        .await
+        .map_err(|e| BotError::S3(e.into()))
+}
+
+/// Keeps the upload's progress next to the video, so if the Lambda gets
+/// killed partway through, the next run finishes it off.
+struct S3UploadStore {
+    bucket: s3::Bucket,
+    path: &'static str,
+}
+
+#[async_trait::async_trait]
+impl UploadStore for S3UploadStore {
+    async fn load(&self) -> Result<Option<UploadState>, SyncError> {
+        let (body, code) = self.bucket.get_object(self.path).await?;
+        match code {
+            200 => Ok(Some(serde_json::from_slice(&body)?)),
+            404 => Ok(None),
+            code => Err(format!("s3 returned {} for {}", code, self.path).into()),
+        }
+    }
+
+    async fn save(&self, state: &UploadState) -> Result<(), SyncError> {
+        let body = serde_json::to_vec(state)?;
+        match self.bucket.put_object(self.path, &body).await? {
+            (_, 200) => Ok(()),
+            (_, code) => Err(format!("s3 returned {} for {}", code, self.path).into()),
+        }
+    }
+
+    async fn clear(&self) -> Result<(), SyncError> {
+        match self.bucket.delete_object(self.path).await? {
+            (_, 200) | (_, 204) | (_, 404) => Ok(()),
+            (_, code) => Err(format!("s3 returned {} for {}", code, self.path).into()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // the real thing, with real credentials and a real ./fox_friday.mp4.
+    // twbot's own tests cover the same path against a mock
     #[test]
+    #[ignore = "needs live credentials and fox_friday.mp4"]
     fn test_authorization() {
         SimpleLogger::new()
             .with_level(log::LevelFilter::Info)
@@ -60,7 +117,7 @@ mod tests {
             .init()
             .unwrap();
 
-        let bot = twbot::Bot::new_from_env().unwrap();
+        let bot = twbot::blocking::Bot::new_from_env().unwrap();
         let fox_video = std::fs::File::open("./fox_friday.mp4").unwrap();
         let fox_video_len = fox_video.metadata().unwrap().len() as usize;
 
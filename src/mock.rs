@@ -0,0 +1,166 @@
+// a tiny blocking HTTP/1.1 server for standing in for remote endpoints
+// in tests. it only understands enough of the protocol for reqwest to talk
+// to it, and closes the connection after every response.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub mod twitter;
+
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// Header lookup, case-insensitive like the real thing.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str())
+    }
+
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        MockResponse {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+
+                thread::spawn(move || {
+                    if let Some(request) = Self::read_request(&stream) {
+                        recorded.lock().unwrap().push(request.clone());
+                        Self::write_response(stream, handler(&request));
+                    }
+                });
+            }
+        });
+
+        MockServer { addr, requests }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn read_request(stream: &TcpStream) -> Option<MockRequest> {
+        let mut reader = BufReader::new(stream);
+
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let mut request_line = line.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+
+            let (name, value) = header.split_once(':')?;
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+
+        let mut body = Vec::new();
+        if let Some(len) = headers.get("content-length") {
+            body.resize(len.parse().ok()?, 0);
+            reader.read_exact(&mut body).ok()?;
+        } else if headers.get("transfer-encoding").map(|v| v.as_str()) == Some("chunked") {
+            loop {
+                line.clear();
+                reader.read_line(&mut line).ok()?;
+                let len = usize::from_str_radix(line.trim(), 16).ok()?;
+                let mut chunk = vec![0; len + 2]; // + the trailing CRLF
+                reader.read_exact(&mut chunk).ok()?;
+                if len == 0 {
+                    break;
+                }
+                body.extend_from_slice(&chunk[..len]);
+            }
+        }
+
+        Some(MockRequest {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+
+    fn write_response(mut stream: TcpStream, response: MockResponse) {
+        let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+        for (name, value) in response.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            response.body.len()
+        ));
+
+        let _ = stream.write_all(head.as_bytes());
+        let _ = stream.write_all(&response.body);
+        let _ = stream.flush();
+    }
+}
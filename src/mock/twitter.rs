@@ -0,0 +1,512 @@
+// just enough of twitter for the bot to run against: the chunked media
+// upload (INIT, APPEND, FINALIZE, STATUS), statuses/update, search and the
+// bearer token endpoint. it checks what the real one would complain about
+// (unknown or unfinished media, missing chunks, duplicate statuses) and
+// answers with the same error codes, and can rate limit any endpoint or
+// have it fall over for a bit, or take its time like a real network.
+
+use super::{MockRequest, MockResponse, MockServer};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockStatus {
+    pub id_str: String,
+    pub text: String,
+    pub media_ids: Vec<String>,
+}
+
+struct Upload {
+    total_bytes: usize,
+    segments: BTreeMap<usize, Vec<u8>>,
+    finalized: bool,
+    /// STATUS checks left before processing's done.
+    checks_left: usize,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    uploads: HashMap<String, Upload>,
+    statuses: Vec<MockStatus>,
+    /// Requests left and when that resets, by endpoint.
+    windows: HashMap<String, (u32, u64)>,
+    /// Failures still to hand out, by endpoint and command.
+    failures: HashMap<(String, Option<String>), (usize, u16)>,
+}
+
+#[derive(Clone)]
+struct Settings {
+    processing_checks: usize,
+    fail_processing: bool,
+    /// STATUS leaves out check_after_secs, and processing_info once it's done.
+    bare_status: bool,
+    /// What INIT says for expires_after_secs, if anything.
+    media_expiry: Option<u64>,
+    /// Requests per window and how long a window is, by endpoint.
+    rate_limits: HashMap<String, (u32, u64)>,
+    /// How long every request takes before it's answered.
+    latency: Duration,
+}
+
+#[derive(Default)]
+struct Concurrency {
+    current: AtomicUsize,
+    max: AtomicUsize,
+}
+
+pub struct MockTwitter {
+    server: MockServer,
+    state: Arc<Mutex<State>>,
+    settings: Arc<Mutex<Settings>>,
+    concurrency: Arc<Concurrency>,
+}
+
+impl MockTwitter {
+    /// Media's processed by the time it's finalized, unless
+    /// `with_processing` says otherwise.
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State {
+            next_id: 1_000,
+            ..Default::default()
+        }));
+        let settings = Arc::new(Mutex::new(Settings {
+            processing_checks: 0,
+            fail_processing: false,
+            bare_status: false,
+            media_expiry: Some(86400),
+            rate_limits: HashMap::new(),
+            latency: Duration::from_secs(0),
+        }));
+        let concurrency = Arc::new(Concurrency::default());
+
+        let (handler_state, handler_settings) = (state.clone(), settings.clone());
+        let handler_concurrency = concurrency.clone();
+        let server = MockServer::start(move |request| {
+            let settings = handler_settings.lock().unwrap().clone();
+            // before taking the lock, so requests can overlap
+            let current = handler_concurrency.current.fetch_add(1, Ordering::SeqCst) + 1;
+            handler_concurrency.max.fetch_max(current, Ordering::SeqCst);
+            thread::sleep(settings.latency);
+            handler_concurrency.current.fetch_sub(1, Ordering::SeqCst);
+
+            let mut state = handler_state.lock().unwrap();
+            if let Some(failure) = failing(&mut state, request) {
+                return failure;
+            }
+            rate_limited(&mut state, &settings, request, |state| {
+                route(state, &settings, request)
+            })
+        });
+
+        MockTwitter {
+            server,
+            state,
+            settings,
+            concurrency,
+        }
+    }
+
+    /// Makes FINALIZE report processing, finishing after this many STATUS
+    /// checks.
+    pub fn with_processing(self, checks: usize) -> Self {
+        self.settings.lock().unwrap().processing_checks = checks;
+        self
+    }
+
+    /// Processing fails instead, like it does for a broken video.
+    pub fn with_failed_processing(self) -> Self {
+        self.settings.lock().unwrap().fail_processing = true;
+        self
+    }
+
+    /// STATUS only says what it has to.
+    pub fn with_bare_status(self) -> Self {
+        self.settings.lock().unwrap().bare_status = true;
+        self
+    }
+
+    /// INIT gives this as expires_after_secs (a day unless this says
+    /// otherwise), or leaves it out for `None`.
+    pub fn with_media_expiry(self, secs: Option<u64>) -> Self {
+        self.settings.lock().unwrap().media_expiry = secs;
+        self
+    }
+
+    /// Allows `limit` calls to `endpoint` every `window_secs`, with the
+    /// x-rate-limit-* headers, and 429s after that.
+    pub fn with_rate_limit(self, endpoint: &str, limit: u32, window_secs: u64) -> Self {
+        self.settings
+            .lock()
+            .unwrap()
+            .rate_limits
+            .insert(endpoint.to_string(), (limit, window_secs));
+        self
+    }
+
+    /// The next `times` calls to `endpoint` (just the ones for `command`,
+    /// for media/upload.json) fail with `status` before doing anything.
+    pub fn with_failures(
+        self,
+        endpoint: &str,
+        command: Option<&str>,
+        times: usize,
+        status: u16,
+    ) -> Self {
+        self.state.lock().unwrap().failures.insert(
+            (endpoint.to_string(), command.map(|c| c.to_string())),
+            (times, status),
+        );
+        self
+    }
+
+    /// Every request takes `latency` to answer.
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.settings.lock().unwrap().latency = latency;
+        self
+    }
+
+    /// Works for both the API and upload hosts.
+    pub fn url(&self) -> String {
+        self.server.url("")
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.server.requests()
+    }
+
+    /// The most requests that were being handled at the same time.
+    pub fn max_concurrency(&self) -> usize {
+        self.concurrency.max.load(Ordering::SeqCst)
+    }
+
+    pub fn statuses(&self) -> Vec<MockStatus> {
+        self.state.lock().unwrap().statuses.clone()
+    }
+
+    /// Everything appended to the upload, in segment order.
+    pub fn media(&self, media_id: &str) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let upload = state.uploads.get(media_id)?;
+        Some(upload.segments.values().flatten().cloned().collect())
+    }
+}
+
+fn route(state: &mut State, settings: &Settings, request: &MockRequest) -> MockResponse {
+    let (path, query) = match request.path.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (request.path.as_str(), ""),
+    };
+
+    match (request.method.as_str(), path) {
+        ("POST", "/oauth2/token") => MockResponse::new(
+            200,
+            json!({"token_type": "bearer", "access_token": "mock-bearer-token"}).to_string(),
+        )
+        .with_header("Content-Type", "application/json"),
+        ("GET", "/1.1/search/tweets.json") => {
+            let params = form(query.as_bytes());
+            let q = params.get("q").cloned().unwrap_or_default();
+            let statuses: Vec<_> = state
+                .statuses
+                .iter()
+                .filter(|s| s.text.contains(&q))
+                .map(|s| json!({"id_str": s.id_str, "text": s.text}))
+                .collect();
+            ok(json!({ "statuses": statuses }))
+        }
+        ("POST", "/1.1/statuses/update.json") => update(state, &form(&request.body)),
+        ("POST", "/1.1/media/upload.json") => {
+            let params = params(request, query);
+            let text = |key: &str| {
+                params
+                    .get(key)
+                    .map(|v| String::from_utf8_lossy(v).to_string())
+            };
+
+            match text("command").as_deref() {
+                Some("INIT") => init(state, settings, text("total_bytes")),
+                Some("APPEND") => append(
+                    state,
+                    text("media_id"),
+                    text("segment_index"),
+                    params.get("media"),
+                ),
+                Some("FINALIZE") => finalize(state, settings, text("media_id")),
+                _ => error(400, 38, "command parameter is missing."),
+            }
+        }
+        ("GET", "/1.1/media/upload.json") => {
+            let params = form(query.as_bytes());
+            match params.get("command").map(|c| c.as_str()) {
+                Some("STATUS") => status(state, settings, params.get("media_id")),
+                _ => error(400, 38, "command parameter is missing."),
+            }
+        }
+        _ => error(404, 34, "Sorry, that page does not exist."),
+    }
+}
+
+fn failing(state: &mut State, request: &MockRequest) -> Option<MockResponse> {
+    let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+    let command = params(request, query)
+        .get("command")
+        .map(|c| String::from_utf8_lossy(c).to_string());
+
+    let (times, status) = state.failures.get_mut(&(path.to_string(), command))?;
+    if *times == 0 {
+        return None;
+    }
+
+    *times -= 1;
+    Some(error(*status, 131, "Internal error"))
+}
+
+fn rate_limited<F>(
+    state: &mut State,
+    settings: &Settings,
+    request: &MockRequest,
+    route: F,
+) -> MockResponse
+where
+    F: FnOnce(&mut State) -> MockResponse,
+{
+    let endpoint = request.path.split('?').next().unwrap_or_default();
+    let (limit, window_secs) = match settings.rate_limits.get(endpoint) {
+        Some(&limit) => limit,
+        None => return route(state),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let window = state
+        .windows
+        .entry(endpoint.to_string())
+        .or_insert((limit, now + window_secs));
+    if now >= window.1 {
+        *window = (limit, now + window_secs);
+    }
+
+    let (remaining, reset) = *window;
+    let response = if remaining == 0 {
+        error(429, 88, "Rate limit exceeded")
+    } else {
+        state.windows.get_mut(endpoint).unwrap().0 -= 1;
+        route(state)
+    };
+
+    response
+        .with_header("x-rate-limit-limit", &limit.to_string())
+        .with_header(
+            "x-rate-limit-remaining",
+            &remaining.saturating_sub(1).to_string(),
+        )
+        .with_header("x-rate-limit-reset", &reset.to_string())
+}
+
+fn init(state: &mut State, settings: &Settings, total_bytes: Option<String>) -> MockResponse {
+    let total_bytes = match total_bytes.and_then(|t| t.parse().ok()) {
+        Some(t) => t,
+        None => return error(400, 38, "total_bytes parameter is missing."),
+    };
+
+    state.next_id += 1;
+    let id = state.next_id.to_string();
+    state.uploads.insert(
+        id.clone(),
+        Upload {
+            total_bytes,
+            segments: BTreeMap::new(),
+            finalized: false,
+            checks_left: settings.processing_checks,
+        },
+    );
+
+    let mut body = json!({"media_id": state.next_id, "media_id_string": id});
+    if let Some(secs) = settings.media_expiry {
+        body["expires_after_secs"] = secs.into();
+    }
+    MockResponse::new(202, body.to_string())
+}
+
+fn append(
+    state: &mut State,
+    media_id: Option<String>,
+    segment: Option<String>,
+    media: Option<&Vec<u8>>,
+) -> MockResponse {
+    let upload = match media_id.and_then(|id| state.uploads.get_mut(&id)) {
+        Some(u) if !u.finalized => u,
+        _ => return error(400, 324, "Invalid media id."),
+    };
+    let (segment, media) = match (segment.and_then(|s| s.parse().ok()), media) {
+        (Some(s), Some(m)) => (s, m),
+        _ => return error(400, 38, "segment_index or media is missing."),
+    };
+
+    upload.segments.insert(segment, media.clone());
+    MockResponse::new(204, "")
+}
+
+fn finalize(state: &mut State, settings: &Settings, media_id: Option<String>) -> MockResponse {
+    let id = media_id.unwrap_or_default();
+    let upload = match state.uploads.get_mut(&id) {
+        Some(u) => u,
+        None => return error(400, 324, "Invalid media id."),
+    };
+
+    let received: usize = upload.segments.values().map(|s| s.len()).sum();
+    let contiguous = upload.segments.keys().copied().eq(0..upload.segments.len());
+    if received != upload.total_bytes || !contiguous {
+        return error(400, 324, "File size exceeds or doesn't match total_bytes.");
+    }
+
+    upload.finalized = true;
+    let mut body = json!({"media_id": id.parse::<u64>().unwrap(), "media_id_string": id});
+    if upload.checks_left > 0 || settings.fail_processing {
+        body["processing_info"] = json!({"state": "pending", "check_after_secs": 1});
+    }
+    ok(body)
+}
+
+fn status(state: &mut State, settings: &Settings, media_id: Option<&String>) -> MockResponse {
+    let id = media_id.cloned().unwrap_or_default();
+    let upload = match state.uploads.get_mut(&id) {
+        Some(u) if u.finalized => u,
+        _ => return error(400, 324, "Invalid media id."),
+    };
+
+    upload.checks_left = upload.checks_left.saturating_sub(1);
+    let processing_info = if settings.fail_processing {
+        json!({
+            "state": "failed",
+            "error": {"code": 1, "name": "InvalidMedia", "message": "Unsupported video format"}
+        })
+    } else if upload.checks_left > 0 && settings.bare_status {
+        json!({"state": "in_progress"})
+    } else if upload.checks_left > 0 {
+        json!({"state": "in_progress", "check_after_secs": 1, "progress_percent": 50})
+    } else if settings.bare_status {
+        return ok(json!({"media_id": id.parse::<u64>().unwrap(), "media_id_string": id}));
+    } else {
+        json!({"state": "succeeded", "progress_percent": 100})
+    };
+
+    ok(json!({
+        "media_id": id.parse::<u64>().unwrap(),
+        "media_id_string": id,
+        "processing_info": processing_info
+    }))
+}
+
+fn update(state: &mut State, params: &HashMap<String, String>) -> MockResponse {
+    let text = params.get("status").cloned().unwrap_or_default();
+    let media_ids: Vec<String> = params
+        .get("media_ids")
+        .map(|ids| ids.split(',').map(|id| id.to_string()).collect())
+        .unwrap_or_default();
+
+    let ready = |id: &String| {
+        state
+            .uploads
+            .get(id)
+            .map(|u| u.finalized && u.checks_left == 0)
+            .unwrap_or(false)
+    };
+    if !media_ids.iter().all(ready) {
+        return error(400, 324, "Invalid media id.");
+    }
+    if state
+        .statuses
+        .iter()
+        .any(|s| s.text == text && s.media_ids == media_ids)
+    {
+        return error(403, 187, "Status is a duplicate.");
+    }
+
+    state.next_id += 1;
+    let posted = MockStatus {
+        id_str: state.next_id.to_string(),
+        text,
+        media_ids,
+    };
+    state.statuses.push(posted.clone());
+    ok(json!({"id_str": posted.id_str, "text": posted.text}))
+}
+
+fn ok(body: serde_json::Value) -> MockResponse {
+    MockResponse::new(200, body.to_string()).with_header("Content-Type", "application/json")
+}
+
+fn error(status: u16, code: usize, message: &str) -> MockResponse {
+    MockResponse::new(
+        status,
+        json!({"errors": [{"code": code, "message": message}]}).to_string(),
+    )
+    .with_header("Content-Type", "application/json")
+}
+
+// the query, form or multipart parameters, whichever the request has
+fn params(request: &MockRequest, query: &str) -> HashMap<String, Vec<u8>> {
+    let is_multipart = request
+        .header("content-type")
+        .map(|t| t.starts_with("multipart/form-data"))
+        .unwrap_or(false);
+    let form = match (is_multipart, request.method.as_str()) {
+        (true, _) => return multipart(request),
+        (false, "GET") => form(query.as_bytes()),
+        (false, _) => form(&request.body),
+    };
+
+    form.into_iter().map(|(k, v)| (k, v.into_bytes())).collect()
+}
+
+fn form(body: &[u8]) -> HashMap<String, String> {
+    url::form_urlencoded::parse(body).into_owned().collect()
+}
+
+// multipart/form-data, the parts reqwest sends anyway: a name per part and
+// no nesting
+fn multipart(request: &MockRequest) -> HashMap<String, Vec<u8>> {
+    let boundary = match request
+        .header("content-type")
+        .and_then(|t| t.split_once("boundary="))
+    {
+        Some((_, boundary)) => format!("--{}", boundary.trim_matches('"')),
+        None => return HashMap::new(),
+    };
+
+    split(&request.body, boundary.as_bytes())
+        .into_iter()
+        .filter_map(|part| {
+            let part = part.strip_prefix(b"\r\n")?;
+            let at = find(part, b"\r\n\r\n")?;
+            let headers = String::from_utf8_lossy(&part[..at]);
+            let content = part[at + 4..].strip_suffix(b"\r\n")?;
+
+            let name = headers.split("name=\"").nth(1)?.split('"').next()?;
+            Some((name.to_string(), content.to_vec()))
+        })
+        .collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn split<'a>(haystack: &'a [u8], needle: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = vec![];
+    let mut rest = haystack;
+    while let Some(at) = find(rest, needle) {
+        parts.push(&rest[..at]);
+        rest = &rest[at + needle.len()..];
+    }
+    parts.push(rest);
+    parts
+}
@@ -1,59 +1,311 @@
 // based off some code i did for the cryptopals challenge some time ago
 // i haven't completed it yet; don't ask for the full repo until i do
+//
+// RFC 4648: the standard alphabet with padding (section 4) for signatures,
+// and the URL-safe one without padding (section 5) for nonces and anything
+// else that ends up in a URL.
 
-const INDEX: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+use std::fmt;
+use std::io::{self, Write};
 
-pub fn bytes_to_base64(mut bytes: Vec<u8>) -> String {
-    let mut result = String::new();
-    let mut padding = 0;
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    alphabet: &'static [u8; 64],
+    pad: bool,
+}
+
+pub const STANDARD: Config = Config {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+    pad: true,
+};
+
+pub const URL_SAFE_NO_PAD: Config = Config {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+    pad: false,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// Something that isn't in the alphabet, at this offset.
+    InvalidByte(usize, u8),
+    /// Padded input that isn't a multiple of 4 long, or a single leftover
+    /// character, which can't encode a whole byte.
+    InvalidLength,
+    /// Padding where there shouldn't be any, or the wrong amount of it.
+    InvalidPadding,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidByte(at, b) => write!(f, "invalid base64 byte {:#04x} at {}", b, at),
+            DecodeError::InvalidLength => write!(f, "invalid base64 length"),
+            DecodeError::InvalidPadding => write!(f, "invalid base64 padding"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub fn bytes_to_base64<T: AsRef<[u8]>>(bytes: T) -> String {
+    encode(bytes, STANDARD)
+}
+
+pub fn encode<T: AsRef<[u8]>>(bytes: T, config: Config) -> String {
+    let bytes = bytes.as_ref();
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        encode_chunk(chunk, config, |c| result.push(c as char));
+    }
+
+    result
+}
+
+// up to three bytes in, two to four characters (plus padding) out
+fn encode_chunk<F: FnMut(u8)>(chunk: &[u8], config: Config, mut out: F) {
+    let mut r: u32 = 0x00;
+    for (i, b) in chunk.iter().enumerate() {
+        r |= (*b as u32) << (0x10 - 0x08 * i);
+    }
+
+    let chars = chunk.len() + 1;
+    for i in 0..chars {
+        out(config.alphabet[((r >> (18 - 6 * i)) & 0x3f) as usize]);
+    }
+    if config.pad {
+        for _ in chars..4 {
+            out(b'=');
+        }
+    }
+}
+
+pub fn decode<T: AsRef<[u8]>>(input: T, config: Config) -> Result<Vec<u8>, DecodeError> {
+    let input = input.as_ref();
 
-    while bytes.len() % 3 != 0 {
-        bytes.push(0x00); // REMEMBER: b'0' is NOT 0x00
-        padding += 1;
+    let data = if config.pad {
+        if input.len() % 4 != 0 {
+            return Err(DecodeError::InvalidLength);
+        }
+        let padding = input.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 {
+            return Err(DecodeError::InvalidPadding);
+        }
+        &input[..input.len() - padding]
+    } else {
+        input
+    };
+    if data.len() % 4 == 1 {
+        return Err(DecodeError::InvalidLength);
     }
 
-    bytes.chunks(3)
-        .map(|b| {
-            let mut r: u32 = 0x00;
-            r += (b[0] as u32) << 0x10;
-            r += (b[1] as u32) << 0x08;
-            r += b[2] as u32;
+    let mut result = Vec::with_capacity(data.len() / 4 * 3 + 2);
+    for (n, chunk) in data.chunks(4).enumerate() {
+        let mut r: u32 = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = match config.alphabet.iter().position(|a| a == c) {
+                Some(v) => v as u32,
+                None if *c == b'=' => return Err(DecodeError::InvalidPadding),
+                None => return Err(DecodeError::InvalidByte(n * 4 + i, *c)),
+            };
+            r |= value << (18 - 6 * i);
+        }
+
+        let bytes = chunk.len() - 1;
+        // the bits past the last whole byte have to be zero, otherwise
+        // there's more than one encoding for the same bytes
+        if r & (0xffffff >> (8 * bytes)) != 0 {
+            return Err(DecodeError::InvalidByte(
+                n * 4 + chunk.len() - 1,
+                chunk[chunk.len() - 1],
+            ));
+        }
+        for i in 0..bytes {
+            result.push((r >> (0x10 - 0x08 * i)) as u8);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Encodes whatever gets written to it straight into `W`, without holding
+/// on to more than two bytes at a time. Call `finish` to write out the last
+/// partial chunk.
+pub struct Encoder<W: Write> {
+    inner: W,
+    config: Config,
+    pending: [u8; 3],
+    pending_len: usize,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, config: Config) -> Self {
+        Encoder {
+            inner,
+            config,
+            pending: [0; 3],
+            pending_len: 0,
+        }
+    }
 
-            let mut t: [u8; 4] = [0; 4];
+    /// Writes out whatever's left, with padding if the config wants it, and
+    /// hands back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.pending_len > 0 {
+            let mut out = Vec::with_capacity(4);
+            encode_chunk(&self.pending[..self.pending_len], self.config, |c| {
+                out.push(c)
+            });
+            self.inner.write_all(&out)?;
+        }
 
-            let mut i = 4; while i > 0 {
-                t[i - 1] = (r & !((r >> 6) << 6)) as u8;
-                r >>= 6;
-                i -= 1;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+
+        // top up a chunk left over from last time first
+        if self.pending_len > 0 {
+            let take = (3 - self.pending_len).min(rest.len());
+            self.pending[self.pending_len..self.pending_len + take].copy_from_slice(&rest[..take]);
+            self.pending_len += take;
+            rest = &rest[take..];
+
+            if self.pending_len < 3 {
+                return Ok(buf.len());
             }
+            let mut out = Vec::with_capacity(4);
+            encode_chunk(&self.pending, self.config, |c| out.push(c));
+            self.inner.write_all(&out)?;
+            self.pending_len = 0;
+        }
+
+        let whole = rest.len() / 3 * 3;
+        let mut out = Vec::with_capacity(whole / 3 * 4);
+        for chunk in rest[..whole].chunks(3) {
+            encode_chunk(chunk, self.config, |c| out.push(c));
+        }
+        self.inner.write_all(&out)?;
 
-            t
-        })
-        .flatten()
-        .for_each(|b| result.push(INDEX[b as usize] as char));
+        let tail = &rest[whole..];
+        self.pending[..tail.len()].copy_from_slice(tail);
+        self.pending_len = tail.len();
 
-    result.truncate(result.len() - padding);
-    while padding > 0 {
-        result.push('=');
-        padding -= 1;
+        Ok(buf.len())
     }
 
-    result
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const TEST_STRING_1: &str = "Sally sells sea shells by the sea shore";
     const TEST_STRING_2: &str = "KNTOBTUT, the unification of KNTO and BTUT";
     const TEST_STRING_3: &str = "how did i get here i am not good with computer";
 
+    // RFC 4648, section 10
+    const RFC_VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
     #[test]
     fn test_base64_encoding() {
-        assert_eq!(bytes_to_base64(TEST_STRING_1.as_bytes().to_vec()), "U2FsbHkgc2VsbHMgc2VhIHNoZWxscyBieSB0aGUgc2VhIHNob3Jl");
-        assert_eq!(bytes_to_base64(TEST_STRING_2.as_bytes().to_vec()), "S05UT0JUVVQsIHRoZSB1bmlmaWNhdGlvbiBvZiBLTlRPIGFuZCBCVFVU");
-        assert_eq!(bytes_to_base64("r".as_bytes().to_vec()), "cg==");
-        assert_eq!(bytes_to_base64(TEST_STRING_3.as_bytes().to_vec()), "aG93IGRpZCBpIGdldCBoZXJlIGkgYW0gbm90IGdvb2Qgd2l0aCBjb21wdXRlcg==");
+        assert_eq!(bytes_to_base64(TEST_STRING_1.as_bytes()), "U2FsbHkgc2VsbHMgc2VhIHNoZWxscyBieSB0aGUgc2VhIHNob3Jl");
+        assert_eq!(bytes_to_base64(TEST_STRING_2.as_bytes()), "S05UT0JUVVQsIHRoZSB1bmlmaWNhdGlvbiBvZiBLTlRPIGFuZCBCVFVU");
+        assert_eq!(bytes_to_base64("r".as_bytes()), "cg==");
+        assert_eq!(bytes_to_base64(TEST_STRING_3.as_bytes()), "aG93IGRpZCBpIGdldCBoZXJlIGkgYW0gbm90IGdvb2Qgd2l0aCBjb21wdXRlcg==");
+    }
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        for (plain, encoded) in RFC_VECTORS {
+            assert_eq!(encode(plain, STANDARD), *encoded);
+            assert_eq!(decode(encoded, STANDARD).unwrap(), plain.as_bytes());
+
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(encode(plain, URL_SAFE_NO_PAD), unpadded);
+            assert_eq!(decode(unpadded, URL_SAFE_NO_PAD).unwrap(), plain.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_url_safe_alphabet() {
+        let bytes = [0xfb, 0xff, 0xbf];
+
+        assert_eq!(encode(bytes, STANDARD), "+/+/");
+        assert_eq!(encode(bytes, URL_SAFE_NO_PAD), "-_-_");
+        assert_eq!(decode("-_-_", URL_SAFE_NO_PAD).unwrap(), bytes);
+        assert_eq!(
+            decode("-_-_", STANDARD),
+            Err(DecodeError::InvalidByte(0, b'-'))
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode("Zg=", STANDARD), Err(DecodeError::InvalidLength));
+        assert_eq!(decode("Z===", STANDARD), Err(DecodeError::InvalidPadding));
+        assert_eq!(decode("Zg==Zg==", STANDARD), Err(DecodeError::InvalidPadding));
+        assert_eq!(decode("Zg==", URL_SAFE_NO_PAD), Err(DecodeError::InvalidPadding));
+        assert_eq!(decode("Zm9vY", URL_SAFE_NO_PAD), Err(DecodeError::InvalidLength));
+        assert_eq!(decode("Zm 9", STANDARD), Err(DecodeError::InvalidByte(2, b' ')));
+        // "Zh==" would be 'f' too if the leftover bits were ignored
+        assert_eq!(decode("Zh==", STANDARD), Err(DecodeError::InvalidByte(1, b'h')));
+    }
+
+    #[test]
+    fn test_streaming_encoder() {
+        let mut encoder = Encoder::new(Vec::new(), STANDARD);
+        for piece in ["S", "ally", " s", "", "ells sea shells by the sea shore"].iter() {
+            encoder.write_all(piece.as_bytes()).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(encoder.finish().unwrap()).unwrap(),
+            "U2FsbHkgc2VsbHMgc2VhIHNoZWxscyBieSB0aGUgc2VhIHNob3Jl"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_round_trip(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            for config in [STANDARD, URL_SAFE_NO_PAD].iter() {
+                let encoded = encode(&bytes, *config);
+                prop_assert_eq!(decode(&encoded, *config).unwrap(), bytes.clone());
+            }
+        }
+
+        #[test]
+        fn prop_streaming_matches_encode(
+            bytes in prop::collection::vec(any::<u8>(), 0..64),
+            splits in prop::collection::vec(0usize..64, 0..6),
+        ) {
+            let mut splits: Vec<usize> = splits.into_iter().map(|s| s.min(bytes.len())).collect();
+            splits.sort_unstable();
+
+            let mut encoder = Encoder::new(Vec::new(), URL_SAFE_NO_PAD);
+            let mut start = 0;
+            for end in splits.into_iter().chain(std::iter::once(bytes.len())) {
+                encoder.write_all(&bytes[start..end]).unwrap();
+                start = end;
+            }
+
+            let streamed = String::from_utf8(encoder.finish().unwrap()).unwrap();
+            prop_assert_eq!(streamed, encode(&bytes, URL_SAFE_NO_PAD));
+        }
     }
 }
@@ -0,0 +1,324 @@
+// app-only auth: OAuth 2.0 client credentials. no user context, so it's only
+// good for read-only endpoints, but there's no signing to do either.
+
+use super::{AsyncAuthenticator, Authenticator};
+use log::debug;
+use oauth2::{
+    basic::BasicClient,
+    reqwest::{async_http_client, http_client},
+    AuthUrl, ClientId, ClientSecret, TokenResponse, TokenUrl,
+};
+use reqwest::{
+    blocking::{Client, Request, Response},
+    header::*,
+    StatusCode,
+};
+use std::fmt;
+use std::sync::Mutex;
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+pub const TOKEN_URL: &str = "https://api.twitter.com/oauth2/token";
+pub const INVALIDATE_TOKEN_URL: &str = "https://api.twitter.com/oauth2/invalidate_token";
+
+pub struct BearerClient {
+    app_key: String,
+    app_secret: String,
+    token_url: String,
+    invalidate_url: String,
+    token: Mutex<Option<String>>,
+}
+
+#[derive(Debug)]
+pub enum BearerError {
+    Status(u16, String),
+}
+
+impl fmt::Display for BearerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BearerError::Status(code, body) => {
+                write!(f, "invalidate_token returned {}: {}", code, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BearerError {}
+
+impl BearerClient {
+    /// Fetches a bearer token from Twitter the first time it's needed.
+    pub fn new(app_key: String, app_secret: String) -> Self {
+        BearerClient {
+            app_key,
+            app_secret,
+            token_url: TOKEN_URL.to_string(),
+            invalidate_url: INVALIDATE_TOKEN_URL.to_string(),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Uses somewhere other than Twitter's oauth2/token and
+    /// oauth2/invalidate_token.
+    pub fn with_endpoints(mut self, token_url: &str, invalidate_url: &str) -> Self {
+        self.token_url = token_url.to_string();
+        self.invalidate_url = invalidate_url.to_string();
+        self
+    }
+
+    /// Starts out with a token we already have, skipping the exchange.
+    pub fn with_bearer_token(self, token: String) -> Self {
+        *self.token.lock().unwrap() = Some(token);
+        self
+    }
+
+    /// The cached token, or a fresh one from the client credentials grant.
+    pub fn bearer_token(&self) -> Result<String, SyncError> {
+        if let Some(token) = self.token.lock().unwrap().as_ref() {
+            return Ok(token.clone());
+        }
+
+        // same as bearer_token_async, the lock isn't held across the request
+        debug!("Requesting a bearer token from {}", self.token_url);
+        let response = self
+            .oauth2_client()?
+            .exchange_client_credentials()
+            .request(http_client)?;
+        let token = response.access_token().secret().clone();
+
+        *self.token.lock().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    /// `bearer_token`, for the async client.
+    pub async fn bearer_token_async(&self) -> Result<String, SyncError> {
+        if let Some(token) = self.token.lock().unwrap().as_ref() {
+            return Ok(token.clone());
+        }
+
+        // not holding the lock across the request, so two callers might both
+        // ask for one. that's fine, client credentials hands out the same token
+        debug!("Requesting a bearer token from {}", self.token_url);
+        let response = self
+            .oauth2_client()?
+            .exchange_client_credentials()
+            .request_async(async_http_client)
+            .await?;
+        let token = response.access_token().secret().clone();
+
+        *self.token.lock().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    fn oauth2_client(&self) -> Result<BasicClient, SyncError> {
+        Ok(BasicClient::new(
+            ClientId::new(self.app_key.clone()),
+            Some(ClientSecret::new(self.app_secret.clone())),
+            // never used, client credentials doesn't involve the user
+            AuthUrl::new(self.token_url.clone())?,
+            Some(TokenUrl::new(self.token_url.clone())?),
+        ))
+    }
+
+    /// Revokes the token with the provider and forgets it, so the next
+    /// request gets a new one. Does nothing if there's no token yet.
+    pub fn invalidate_token(&self, http: &Client) -> Result<(), SyncError> {
+        let token = match self.token.lock().unwrap().take() {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+
+        let response = http
+            .post(&self.invalidate_url)
+            .basic_auth(&self.app_key, Some(&self.app_secret))
+            .form(&[("access_token", token.as_str())])
+            .send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Box::new(BearerError::Status(
+                status.as_u16(),
+                response.text()?,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Drops the cached token without telling the provider.
+    fn forget_token(&self) -> bool {
+        self.token.lock().unwrap().take().is_some()
+    }
+}
+
+impl Authenticator for BearerClient {
+    fn auth_request(&self, mut request: Request) -> Result<Request, SyncError> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", self.bearer_token()?))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+
+        Ok(request)
+    }
+
+    /// Sends the request, and if the token's been revoked or expired out from
+    /// under us, gets a new one and tries once more.
+    fn execute(&self, http: &Client, request: Request) -> Result<Response, SyncError> {
+        let retry = request.try_clone();
+        let response = http.execute(self.auth_request(request)?)?;
+
+        match retry {
+            Some(request)
+                if response.status() == StatusCode::UNAUTHORIZED && self.forget_token() =>
+            {
+                Ok(http.execute(self.auth_request(request)?)?)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncAuthenticator for BearerClient {
+    async fn auth_request_async(
+        &self,
+        mut request: reqwest::Request,
+    ) -> Result<reqwest::Request, SyncError> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", self.bearer_token_async().await?))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+
+        Ok(request)
+    }
+
+    async fn execute_async(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, SyncError> {
+        let retry = request.try_clone();
+        let response = http
+            .execute(self.auth_request_async(request).await?)
+            .await?;
+
+        match retry {
+            Some(request)
+                if response.status() == StatusCode::UNAUTHORIZED && self.forget_token() =>
+            {
+                Ok(http
+                    .execute(self.auth_request_async(request).await?)
+                    .await?)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    // a token endpoint that hands out token-1, token-2, ... and an api
+    // endpoint that only takes the latest one
+    fn server() -> MockServer {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let issued = AtomicUsize::new(0);
+
+        MockServer::start(move |request| match request.path.as_str() {
+            "/oauth2/token" => {
+                let n = issued.fetch_add(1, Ordering::SeqCst) + 1;
+                MockResponse::new(
+                    200,
+                    format!(r#"{{"token_type":"bearer","access_token":"token-{}"}}"#, n),
+                )
+                .with_header("Content-Type", "application/json")
+            }
+            "/oauth2/invalidate_token" => MockResponse::new(200, "{}"),
+            _ => {
+                let latest = format!("Bearer token-{}", issued.load(Ordering::SeqCst));
+                if request.header("authorization") == Some(latest.as_str()) {
+                    MockResponse::new(200, "[]")
+                } else {
+                    MockResponse::new(401, "")
+                }
+            }
+        })
+    }
+
+    fn client(server: &MockServer) -> BearerClient {
+        BearerClient::new("app key".to_string(), "app-secret".to_string()).with_endpoints(
+            &server.url("/oauth2/token"),
+            &server.url("/oauth2/invalidate_token"),
+        )
+    }
+
+    #[test]
+    fn test_client_credentials_exchange() {
+        let server = server();
+        let client = client(&server);
+
+        assert_eq!(client.bearer_token().unwrap(), "token-1");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert!(requests[0]
+            .body_text()
+            .contains("grant_type=client_credentials"));
+        // "app key:app-secret", with the key form-encoded first
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Basic YXBwK2tleTphcHAtc2VjcmV0")
+        );
+    }
+
+    #[test]
+    fn test_token_is_cached() {
+        let server = server();
+        let client = client(&server);
+        let http = Client::new();
+
+        for _ in 0..3 {
+            let request = http.get(server.url("/search")).build().unwrap();
+            let response = client.execute(&http, request).unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            vec!["/oauth2/token", "/search", "/search", "/search"]
+        );
+    }
+
+    #[test]
+    fn test_invalidate_token() {
+        let server = server();
+        let client = client(&server);
+        let http = Client::new();
+
+        client.bearer_token().unwrap();
+        client.invalidate_token(&http).unwrap();
+
+        let invalidate = &server.requests()[1];
+        assert_eq!(invalidate.path, "/oauth2/invalidate_token");
+        assert_eq!(invalidate.body_text(), "access_token=token-1");
+
+        // the next request picks up a new token
+        assert_eq!(client.bearer_token().unwrap(), "token-2");
+        // and with nothing cached, there's nothing to invalidate
+        client.forget_token();
+        client.invalidate_token(&http).unwrap();
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_stale_token_is_replaced() {
+        let server = server();
+        let client = client(&server).with_bearer_token("revoked".to_string());
+        let http = Client::new();
+
+        let request = http.get(server.url("/search")).build().unwrap();
+        let response = client.execute(&http, request).unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, vec!["/search", "/oauth2/token", "/search"]);
+    }
+}
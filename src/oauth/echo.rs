@@ -0,0 +1,254 @@
+// OAuth Echo: handing someone else a pre-signed verify_credentials request,
+// so they can find out who we are by sending it themselves, without ever
+// seeing our secrets. OAuthClient::echo_headers makes the headers; the
+// validator here is the other end, for the services that take them.
+
+use log::debug;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, AUTHORIZATION},
+};
+use serde::Deserialize;
+use std::fmt;
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+pub const VERIFY_CREDENTIALS_URL: &str =
+    "https://api.twitter.com/1.1/account/verify_credentials.json";
+
+/// Where the receiving end should send the echo.
+pub const SERVICE_PROVIDER: &str = "x-auth-service-provider";
+/// The Authorization header for a request to the service provider.
+pub const VERIFY_CREDENTIALS_AUTHORIZATION: &str = "x-verify-credentials-authorization";
+
+/// What the provider says about whoever signed the echo.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct EchoUser {
+    pub id_str: String,
+    pub screen_name: String,
+}
+
+#[derive(Debug)]
+pub enum EchoError {
+    MissingHeader(&'static str),
+    /// The echo points somewhere we haven't been told to trust.
+    UntrustedProvider(String),
+    /// The provider didn't accept the signature.
+    Rejected(u16, String),
+}
+
+impl fmt::Display for EchoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EchoError::MissingHeader(name) => write!(f, "missing {} header", name),
+            EchoError::UntrustedProvider(url) => write!(f, "untrusted service provider {}", url),
+            EchoError::Rejected(code, body) => {
+                write!(f, "service provider returned {}: {}", code, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EchoError {}
+
+/// Checks echoes by passing them on to the service provider.
+pub struct EchoValidator {
+    http: Client,
+    providers: Vec<String>,
+}
+
+impl Default for EchoValidator {
+    fn default() -> Self {
+        EchoValidator {
+            http: Client::new(),
+            providers: vec![VERIFY_CREDENTIALS_URL.to_string()],
+        }
+    }
+}
+
+impl EchoValidator {
+    /// Only trusts Twitter's verify_credentials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Accepts echoes sent to this url too. Whatever it answers is taken as
+    /// the caller's identity, so only add ones you'd believe.
+    pub fn with_service_provider(mut self, url: &str) -> Self {
+        self.providers.push(url.to_string());
+        self
+    }
+
+    /// Sends the echo in `headers` to its service provider, and gives back
+    /// who signed it. The provider has to be one we trust; otherwise anyone
+    /// could point us at a server that vouches for them.
+    pub fn validate(&self, headers: &HeaderMap) -> Result<EchoUser, SyncError> {
+        let provider = Self::header(headers, SERVICE_PROVIDER)?;
+        let authorization = Self::header(headers, VERIFY_CREDENTIALS_AUTHORIZATION)?;
+
+        if !self.providers.iter().any(|p| p == provider) {
+            return Err(Box::new(EchoError::UntrustedProvider(provider.to_string())));
+        }
+
+        debug!("Validating echo with {}", provider);
+        let response = self
+            .http
+            .get(provider)
+            .header(AUTHORIZATION, authorization)
+            .send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Box::new(EchoError::Rejected(
+                status.as_u16(),
+                response.text()?,
+            )));
+        }
+
+        Ok(response.json()?)
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: &'static str) -> Result<&'a str, EchoError> {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or(EchoError::MissingHeader(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::client::OAuthClient;
+    use super::super::verifier::{SignedRequest, StaticSecrets, Verifier};
+    use super::*;
+    use crate::mock::{MockRequest, MockResponse, MockServer};
+    use reqwest::header::{HeaderName, HeaderValue};
+    use reqwest::{Method, StatusCode, Url};
+    use std::sync::{Arc, Mutex};
+
+    fn oauth(secret: &str) -> OAuthClient {
+        OAuthClient::new(
+            "app-key".to_string(),
+            "app-secret".to_string(),
+            "user-token".to_string(),
+            secret.to_string(),
+        )
+    }
+
+    // stands in for twitter's verify_credentials
+    fn provider() -> MockServer {
+        let verifier = Verifier::new(
+            StaticSecrets::new()
+                .with_consumer("app-key", "app-secret")
+                .with_token("app-key", "user-token", "user-secret"),
+        );
+        let url = Arc::new(Mutex::new(None::<Url>));
+
+        let server_url = url.clone();
+        let server = MockServer::start(move |request| {
+            let url = server_url.lock().unwrap().clone().unwrap();
+            let verified = verifier.verify(&SignedRequest {
+                method: &Method::GET,
+                url: &url,
+                authorization: request.header("authorization").unwrap_or(""),
+                content_type: None,
+                body: &[],
+            });
+
+            match verified {
+                Ok(_) => MockResponse::new(200, r#"{"id_str":"12345","screen_name":"foxfriday"}"#)
+                    .with_header("Content-Type", "application/json"),
+                Err(e) => MockResponse::new(401, e.to_string()),
+            }
+        });
+        *url.lock().unwrap() = Some(Url::parse(&server.url("/verify_credentials.json")).unwrap());
+
+        server
+    }
+
+    fn headers(request: &MockRequest) -> HeaderMap {
+        request
+            .headers
+            .iter()
+            .map(|(k, v)| {
+                (
+                    HeaderName::from_bytes(k.as_bytes()).unwrap(),
+                    HeaderValue::from_str(v).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_echo_round_trip() {
+        let provider = provider();
+        let verify_url = provider.url("/verify_credentials.json");
+
+        // a media host that checks the echo before taking the upload
+        let validator = EchoValidator::new().with_service_provider(&verify_url);
+        let host = MockServer::start(move |request| match validator.validate(&headers(request)) {
+            Ok(user) => MockResponse::new(200, user.screen_name),
+            Err(e) => MockResponse::new(401, e.to_string()),
+        });
+
+        let http = Client::new();
+        let request = http
+            .post(host.url("/upload"))
+            .body("a fox")
+            .build()
+            .unwrap();
+        let request = oauth("user-secret")
+            .auth_echo_request(request, &verify_url)
+            .unwrap();
+        let response = http.execute(request).unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().unwrap(), "foxfriday");
+        // the host never gets an Authorization header of its own
+        assert!(host.requests()[0].header("authorization").is_none());
+        assert_eq!(provider.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_echo_rejected_by_provider() {
+        let provider = provider();
+        let verify_url = provider.url("/verify_credentials.json");
+        let validator = EchoValidator::new().with_service_provider(&verify_url);
+
+        let headers = oauth("wrong-secret").echo_headers(&verify_url).unwrap();
+        let err = validator.validate(&headers).unwrap_err();
+
+        match err.downcast_ref::<EchoError>() {
+            Some(EchoError::Rejected(401, _)) => {}
+            other => panic!("expected a 401, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_untrusted_provider() {
+        let provider = provider();
+        let verify_url = provider.url("/verify_credentials.json");
+
+        let headers = oauth("user-secret").echo_headers(&verify_url).unwrap();
+        let err = EchoValidator::new().validate(&headers).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<EchoError>(),
+            Some(EchoError::UntrustedProvider(_))
+        ));
+        // and it never got asked
+        assert!(provider.requests().is_empty());
+
+        let err = EchoValidator::new()
+            .validate(&HeaderMap::new())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EchoError>(),
+            Some(EchoError::MissingHeader(SERVICE_PROVIDER))
+        ));
+    }
+}
@@ -0,0 +1,425 @@
+// the Authorization: OAuth ... header, RFC 5849 section 3.5.1. values are
+// percent-encoded and then quoted, except for the realm, which is a plain
+// quoted-string.
+
+use super::parameter::Parameter;
+use super::redact;
+use percent_encoding::{percent_decode_str, AsciiSet};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct OAuthHeader {
+    pub realm: Option<String>,
+    pub consumer_key: String,
+    pub token: Option<String>,
+    pub signature_method: String,
+    pub signature: String,
+    pub timestamp: u64,
+    pub nonce: String,
+    pub version: Option<String>,
+    pub callback: Option<String>,
+    pub verifier: Option<String>,
+    /// Any other oauth_* parameters, which still get signed.
+    pub extra: Vec<Parameter>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    NotOAuth,
+    Malformed(String),
+    UnterminatedQuote(String),
+    MissingField(&'static str),
+    DuplicateField(String),
+    /// Something other than the realm or an oauth_* parameter, which
+    /// doesn't belong in the header.
+    UnexpectedField(String),
+    InvalidEncoding(String),
+    InvalidTimestamp(String),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::NotOAuth => write!(f, "not an OAuth authorization header"),
+            HeaderError::Malformed(reason) => write!(f, "malformed OAuth header: {}", reason),
+            HeaderError::UnterminatedQuote(key) => write!(f, "value of {} is missing its closing quote", key),
+            HeaderError::MissingField(key) => write!(f, "OAuth header is missing {}", key),
+            HeaderError::DuplicateField(key) => write!(f, "OAuth header has {} more than once", key),
+            HeaderError::UnexpectedField(key) => {
+                write!(f, "{} is not an OAuth protocol parameter", key)
+            }
+            HeaderError::InvalidEncoding(key) => {
+                write!(f, "value of {} is not percent-encoded utf-8", key)
+            }
+            HeaderError::InvalidTimestamp(value) => write!(f, "{} is not a valid timestamp", value),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl fmt::Debug for OAuthHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mask = |v: &Option<String>| v.as_ref().map(|_| redact::MASK);
+
+        f.debug_struct("OAuthHeader")
+            .field("realm", &self.realm)
+            .field("consumer_key", &redact::MASK)
+            .field("token", &mask(&self.token))
+            .field("signature_method", &self.signature_method)
+            .field("signature", &redact::MASK)
+            .field("timestamp", &self.timestamp)
+            .field("nonce", &redact::MASK)
+            .field("version", &self.version)
+            .field("callback", &self.callback)
+            .field("verifier", &mask(&self.verifier))
+            .field("extra", &self.extra)
+            .finish()
+    }
+}
+
+impl OAuthHeader {
+    /// The parameters that go into the signature base string: everything
+    /// but the realm and the signature itself.
+    pub fn params(&self) -> Vec<Parameter> {
+        let mut params = vec![
+            Parameter::new("oauth_consumer_key", self.consumer_key.as_str()),
+            Parameter::new("oauth_signature_method", self.signature_method.as_str()),
+            Parameter::new("oauth_timestamp", self.timestamp.to_string()),
+            Parameter::new("oauth_nonce", self.nonce.as_str()),
+        ];
+
+        let optional = [
+            ("oauth_token", &self.token),
+            ("oauth_version", &self.version),
+            ("oauth_callback", &self.callback),
+            ("oauth_verifier", &self.verifier),
+        ];
+        for (key, value) in optional.iter() {
+            if let Some(value) = value {
+                params.push(Parameter::new(*key, value.as_str()));
+            }
+        }
+
+        params.extend(self.extra.iter().cloned());
+        params.sort();
+        params
+    }
+
+    // splits `key="value", key="value"` into raw (still encoded) pairs
+    fn split_fields(fields: &str) -> Result<Vec<(String, String)>, HeaderError> {
+        let mut result = Vec::new();
+        let mut chars = fields.chars().peekable();
+
+        loop {
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() || *c == ',' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let mut key = String::new();
+            loop {
+                match chars.next() {
+                    Some('=') => break,
+                    Some(c) => key.push(c),
+                    None => {
+                        return Err(HeaderError::Malformed(format!(
+                            "{} has no value",
+                            key.trim()
+                        )))
+                    }
+                }
+            }
+            let key = key.trim().to_string();
+
+            while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                chars.next();
+            }
+            if chars.next() != Some('"') {
+                return Err(HeaderError::Malformed(format!("{} is not quoted", key)));
+            }
+
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c) => value.push(c),
+                        None => return Err(HeaderError::UnterminatedQuote(key)),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(HeaderError::UnterminatedQuote(key)),
+                }
+            }
+
+            while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                chars.next();
+            }
+            match chars.next() {
+                Some(',') | None => {}
+                Some(c) => {
+                    return Err(HeaderError::Malformed(format!(
+                        "expected ',' after {}, found '{}'",
+                        key, c
+                    )))
+                }
+            }
+
+            result.push((key, value));
+        }
+
+        Ok(result)
+    }
+}
+
+impl OAuthHeader {
+    /// The header value, percent-encoding with `encoding` instead of the
+    /// RFC 5849 set. `to_string()` is this with the usual one.
+    pub fn to_header_value(&self, encoding: &'static AsciiSet) -> String {
+        let mut fields = Vec::new();
+
+        if let Some(realm) = self.realm.as_ref() {
+            let escaped = realm.replace('\\', "\\\\").replace('"', "\\\"");
+            fields.push(format!("realm=\"{}\"", escaped));
+        }
+
+        let mut params = self.params();
+        params.push(Parameter::new("oauth_signature", self.signature.as_str()));
+        params.sort();
+        fields.extend(params.iter().map(|p| {
+            format!(
+                "{}=\"{}\"",
+                p.encoded_key_with(encoding),
+                p.encoded_value_with(encoding)
+            )
+        }));
+
+        format!("OAuth {}", fields.join(","))
+    }
+}
+
+impl fmt::Display for OAuthHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_header_value(super::TWITTER_ENCODING))
+    }
+}
+
+impl FromStr for OAuthHeader {
+    type Err = HeaderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (scheme, fields) = s.split_at(s.find(char::is_whitespace).unwrap_or(s.len()));
+        if !scheme.eq_ignore_ascii_case("OAuth") {
+            return Err(HeaderError::NotOAuth);
+        }
+
+        let mut header = OAuthHeader::default();
+        let mut seen: Vec<String> = Vec::new();
+        let mut timestamp = None;
+
+        for (key, raw) in Self::split_fields(fields)? {
+            if seen.contains(&key) {
+                return Err(HeaderError::DuplicateField(key));
+            }
+            seen.push(key.clone());
+
+            if key == "realm" {
+                header.realm = Some(raw);
+                continue;
+            }
+            // RFC 5849 section 3.5.1, only protocol parameters go in the
+            // header. anything else would end up signed as if it were one
+            if !key.starts_with("oauth_") {
+                return Err(HeaderError::UnexpectedField(key));
+            }
+
+            let value = percent_decode_str(&raw)
+                .decode_utf8()
+                .map_err(|_| HeaderError::InvalidEncoding(key.clone()))?
+                .to_string();
+
+            match key.as_str() {
+                "oauth_consumer_key" => header.consumer_key = value,
+                "oauth_token" => header.token = Some(value),
+                "oauth_signature_method" => header.signature_method = value,
+                "oauth_signature" => header.signature = value,
+                "oauth_timestamp" => timestamp = Some(value),
+                "oauth_nonce" => header.nonce = value,
+                "oauth_version" => header.version = Some(value),
+                "oauth_callback" => header.callback = Some(value),
+                "oauth_verifier" => header.verifier = Some(value),
+                _ => header.extra.push(Parameter::new(key.as_str(), value)),
+            }
+        }
+
+        let required = [
+            "oauth_consumer_key",
+            "oauth_signature_method",
+            "oauth_signature",
+            "oauth_timestamp",
+            "oauth_nonce",
+        ];
+        for key in required.iter() {
+            if !seen.iter().any(|k| k == key) {
+                return Err(HeaderError::MissingField(key));
+            }
+        }
+
+        let timestamp = timestamp.unwrap_or_default();
+        header.timestamp = timestamp
+            .parse()
+            .map_err(|_| HeaderError::InvalidTimestamp(timestamp))?;
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn twitter_example() -> OAuthHeader {
+        OAuthHeader {
+            consumer_key: "xvz1evFS4wEEPTGEFPHBog".to_string(),
+            token: Some("370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb".to_string()),
+            signature_method: "HMAC-SHA1".to_string(),
+            signature: "tnnArxj06cWHq44gCs1OSKk/jLY=".to_string(),
+            timestamp: 1318622958,
+            nonce: "kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg".to_string(),
+            version: Some("1.0".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_twitter_example() {
+        // straight from the twitter docs, spaces and all
+        let header: OAuthHeader = "OAuth oauth_consumer_key=\"xvz1evFS4wEEPTGEFPHBog\", oauth_nonce=\"kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg\", oauth_signature=\"tnnArxj06cWHq44gCs1OSKk%2FjLY%3D\", oauth_signature_method=\"HMAC-SHA1\", oauth_timestamp=\"1318622958\", oauth_token=\"370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb\", oauth_version=\"1.0\""
+            .parse()
+            .unwrap();
+
+        assert_eq!(header, twitter_example());
+    }
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(
+            twitter_example().to_string(),
+            "OAuth oauth_consumer_key=\"xvz1evFS4wEEPTGEFPHBog\",oauth_nonce=\"kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg\",oauth_signature=\"tnnArxj06cWHq44gCs1OSKk%2FjLY%3D\",oauth_signature_method=\"HMAC-SHA1\",oauth_timestamp=\"1318622958\",oauth_token=\"370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb\",oauth_version=\"1.0\""
+        );
+    }
+
+    #[test]
+    fn test_optional_fields_round_trip() {
+        let header = OAuthHeader {
+            realm: Some("Photos, \"the\" \\ realm".to_string()),
+            callback: Some("http://localhost:8080/callback?a=1&b=two words".to_string()),
+            verifier: Some("hfdp7dh39dks9884".to_string()),
+            token: None,
+            extra: vec![Parameter::new("oauth_body_hash", "2jmj7l5rSw0yVb/vlWAYkK/YBwk=")],
+            ..twitter_example()
+        };
+
+        let serialized = header.to_string();
+        assert!(serialized.starts_with("OAuth realm=\"Photos, \\\"the\\\" \\\\ realm\","));
+        assert!(serialized.contains(
+            "oauth_callback=\"http%3A%2F%2Flocalhost%3A8080%2Fcallback%3Fa%3D1%26b%3Dtwo%20words\""
+        ));
+        assert_eq!(serialized.parse::<OAuthHeader>().unwrap(), header);
+    }
+
+    #[test]
+    fn test_params_skip_realm_and_signature() {
+        let header = OAuthHeader {
+            realm: Some("Example".to_string()),
+            ..twitter_example()
+        };
+
+        assert_eq!(
+            Parameter::join(header.params()),
+            "oauth_consumer_key=xvz1evFS4wEEPTGEFPHBog&oauth_nonce=kYjzVBB8Y0ZFabxSWbWovY3uYSQ2pTgmZeNu2VS4cg&oauth_signature_method=HMAC-SHA1&oauth_timestamp=1318622958&oauth_token=370773112-GmHxMAgYyLbNEtIKZeRNFsMKPR9EyMZeS9weJAEb&oauth_version=1.0"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |s: &str| s.parse::<OAuthHeader>();
+
+        assert_eq!(parse("Bearer AAAA"), Err(HeaderError::NotOAuth));
+        assert_eq!(
+            parse("OAuth oauth_consumer_key"),
+            Err(HeaderError::Malformed("oauth_consumer_key has no value".to_string()))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=abc"),
+            Err(HeaderError::Malformed("oauth_consumer_key is not quoted".to_string()))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=\"abc"),
+            Err(HeaderError::UnterminatedQuote("oauth_consumer_key".to_string()))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=\"abc\" oauth_nonce=\"def\""),
+            Err(HeaderError::Malformed(
+                "expected ',' after oauth_consumer_key, found 'o'".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=\"a\", oauth_consumer_key=\"b\""),
+            Err(HeaderError::DuplicateField("oauth_consumer_key".to_string()))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=\"abc\", status=\"hello\""),
+            Err(HeaderError::UnexpectedField("status".to_string()))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=\"%FF\""),
+            Err(HeaderError::InvalidEncoding("oauth_consumer_key".to_string()))
+        );
+        assert_eq!(
+            parse("OAuth oauth_consumer_key=\"abc\""),
+            Err(HeaderError::MissingField("oauth_signature_method"))
+        );
+
+        let bad_timestamp = twitter_example()
+            .to_string()
+            .replace("1318622958", "yesterday");
+        assert_eq!(
+            parse(&bad_timestamp),
+            Err(HeaderError::InvalidTimestamp("yesterday".to_string()))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_round_trip(
+            realm in proptest::option::of("[ -~]{0,12}"),
+            token in proptest::option::of("\\PC{0,12}"),
+            callback in proptest::option::of("\\PC{0,24}"),
+            signature in "\\PC{0,28}",
+            nonce in "\\PC{0,16}",
+            timestamp in any::<u64>(),
+        ) {
+            let header = OAuthHeader {
+                realm,
+                token,
+                callback,
+                signature,
+                nonce,
+                timestamp,
+                ..twitter_example()
+            };
+
+            prop_assert_eq!(header.to_string().parse::<OAuthHeader>(), Ok(header));
+        }
+    }
+}
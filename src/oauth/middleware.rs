@@ -0,0 +1,174 @@
+// signs everything that goes through an async reqwest client, so the async
+// side doesn't have to remember to call auth_async_request itself.
+
+use super::client::OAuthClient;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Error, Middleware, Next, Result};
+use std::sync::Arc;
+use task_local_extensions::Extensions;
+
+pub struct OAuthMiddleware {
+    oauth: Arc<OAuthClient>,
+}
+
+impl OAuthMiddleware {
+    pub fn new(oauth: OAuthClient) -> Self {
+        Self::shared(Arc::new(oauth))
+    }
+
+    /// For when the same client signs for something else too.
+    pub fn shared(oauth: Arc<OAuthClient>) -> Self {
+        OAuthMiddleware { oauth }
+    }
+
+    fn sign(&self, request: Request) -> Result<Request> {
+        self.oauth
+            .auth_async_request(request)
+            .map_err(|e| Error::Middleware(anyhow::anyhow!(e)))
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for OAuthMiddleware {
+    /// Signs and sends the request, signing it again with a corrected
+    /// timestamp if the provider says our clock is off, like
+    /// `OAuthClient::execute` does.
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let retry = request.try_clone();
+        let response = next.clone().run(self.sign(request)?, extensions).await?;
+
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        match retry {
+            Some(request) if self.oauth.correct_skew(response.headers()) => {
+                next.run(self.sign(request)?, extensions).await
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Wraps `http` so every request it sends gets signed by `oauth`.
+pub fn signing_client(http: reqwest::Client, oauth: OAuthClient) -> ClientWithMiddleware {
+    ClientBuilder::new(http)
+        .with(OAuthMiddleware::new(oauth))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::header::OAuthHeader;
+    use super::super::verifier::{SignedRequest, StaticSecrets, Verifier};
+    use super::*;
+    use crate::mock::{MockRequest, MockResponse, MockServer};
+    use reqwest::{Method, Url};
+    use std::time::{Duration, SystemTime};
+
+    fn oauth() -> OAuthClient {
+        OAuthClient::new(
+            "app-key".to_string(),
+            "app-secret".to_string(),
+            "user-token".to_string(),
+            "user-secret".to_string(),
+        )
+    }
+
+    // checks what the mock server got the same way a provider would
+    fn verify(server: &MockServer, request: &MockRequest) -> bool {
+        let verifier = Verifier::new(
+            StaticSecrets::new()
+                .with_consumer("app-key", "app-secret")
+                .with_token("app-key", "user-token", "user-secret"),
+        );
+        let url = Url::parse(&server.url(&request.path)).unwrap();
+
+        verifier
+            .verify(&SignedRequest {
+                method: &request.method.parse::<Method>().unwrap(),
+                url: &url,
+                authorization: request.header("authorization").unwrap_or(""),
+                content_type: request.header("content-type"),
+                body: &request.body,
+            })
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_async_request_signing() {
+        let server = MockServer::start(|_| MockResponse::new(200, ""));
+        let http = reqwest::Client::new();
+
+        let request = http
+            .post(server.url("/1.1/statuses/update.json?include_entities=true"))
+            .form(&[("status", "it's fox friday!")])
+            .build()
+            .unwrap();
+        let request = oauth().auth_async_request(request).unwrap();
+        http.execute(request).await.unwrap();
+
+        let received = &server.requests()[0];
+        assert!(verify(&server, received));
+    }
+
+    #[tokio::test]
+    async fn test_signing_client() {
+        let server = MockServer::start(|_| MockResponse::new(200, ""));
+        let client = signing_client(reqwest::Client::new(), oauth());
+
+        client
+            .get(server.url("/1.1/account/verify_credentials.json?skip_status=true"))
+            .send()
+            .await
+            .unwrap();
+        client
+            .post(server.url("/1.1/statuses/update.json"))
+            .form(&[("status", "hi")])
+            .send()
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| verify(&server, r)));
+    }
+
+    #[tokio::test]
+    async fn test_signing_client_corrects_skew() {
+        // an hour ahead of us, and turning down anything that isn't close
+        let server = MockServer::start(|request| {
+            let server_time = SystemTime::now() + Duration::from_secs(3600);
+            let server_secs = server_time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let timestamp = request
+                .header("authorization")
+                .and_then(|a| a.parse::<OAuthHeader>().ok())
+                .map_or(0, |h| h.timestamp);
+
+            if timestamp.abs_diff(server_secs) < 300 {
+                MockResponse::new(200, "")
+            } else {
+                MockResponse::new(401, "")
+                    .with_header("Date", &httpdate::fmt_http_date(server_time))
+            }
+        });
+        let oauth = Arc::new(oauth());
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(OAuthMiddleware::shared(oauth.clone()))
+            .build();
+
+        let response = client.get(server.url("/")).send().await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.requests().len(), 2);
+        assert!((oauth.skew() - 3600).abs() <= 2);
+    }
+}
@@ -16,16 +16,61 @@ use std::time::SystemTime;
 */
 
 use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+use reqwest::blocking::{Client, Request, Response};
 pub mod base64;
-pub mod parameter;
+pub mod bearer;
 pub mod client;
-
-const TWITTER_ENCODING: &AsciiSet = &NON_ALPHANUMERIC
+pub mod echo;
+pub mod header;
+pub mod middleware;
+pub mod parameter;
+pub mod pkce;
+pub mod provider;
+pub mod redact;
+pub mod signature;
+pub mod sources;
+pub mod verifier;
+
+pub const TWITTER_ENCODING: &AsciiSet = &NON_ALPHANUMERIC
 .remove(b'_')
 .remove(b'-')
 .remove(b'~')
 .remove(b'.');
 
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+/// Something that can put credentials on a request: OAuth 1.0a user context
+/// with `client::OAuthClient`, or an app-only bearer token with
+/// `bearer::BearerClient`.
+pub trait Authenticator: Send + Sync {
+    fn auth_request(&self, request: Request) -> Result<Request, SyncError>;
+
+    /// Authorizes and sends the request. Implementations can override this
+    /// to retry when the credentials turn out to be stale.
+    fn execute(&self, http: &Client, request: Request) -> Result<Response, SyncError> {
+        Ok(http.execute(self.auth_request(request)?)?)
+    }
+}
+
+/// `Authenticator`, for the async client. Anything that might have to fetch
+/// or refresh a token does it without blocking the runtime.
+#[async_trait::async_trait]
+pub trait AsyncAuthenticator: Send + Sync {
+    async fn auth_request_async(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Request, SyncError>;
+
+    async fn execute_async(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, SyncError> {
+        let request = self.auth_request_async(request).await?;
+        Ok(http.execute(request).await?)
+    }
+}
+
 /*
 pub struct OAuthClient {
     app_key: String,
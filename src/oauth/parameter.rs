@@ -1,7 +1,11 @@
-use percent_encoding::utf8_percent_encode;
+use percent_encoding::{utf8_percent_encode, AsciiSet};
 use std::cmp::Ordering;
+use std::fmt;
 
-#[derive(Clone, Debug, Eq)]
+/// A single name/value pair going into the signature. Keys can repeat, and
+/// pairs sort the way RFC 5849 wants them to: by encoded key, then by
+/// encoded value.
+#[derive(Clone, PartialEq, Eq)]
 pub struct Parameter {
     key: String,
     value: String,
@@ -18,30 +22,81 @@ impl Parameter {
         }
     }
 
-    // putting this here feels... messy.
-    // however, it's the only way to get to the internal non-public key/value
+    /// Whether a body with this content type has parameters that get signed.
+    pub fn is_form(content_type: Option<&str>) -> bool {
+        content_type
+            .map(|v| v.starts_with("application/x-www-form-urlencoded"))
+            .unwrap_or(false)
+    }
+
+    /// The pairs in an application/x-www-form-urlencoded body, in order.
+    pub fn from_form(body: &[u8]) -> Vec<Self> {
+        url::form_urlencoded::parse(body)
+            .map(|(k, v)| Parameter::new(k, v))
+            .collect()
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn encoded_key(&self) -> String {
+        self.encoded_key_with(super::TWITTER_ENCODING)
+    }
+
+    pub fn encoded_value(&self) -> String {
+        self.encoded_value_with(super::TWITTER_ENCODING)
+    }
+
+    /// For providers that don't quite encode the way RFC 5849 says to.
+    pub fn encoded_key_with(&self, encoding: &'static AsciiSet) -> String {
+        utf8_percent_encode(&self.key, encoding).to_string()
+    }
+
+    pub fn encoded_value_with(&self, encoding: &'static AsciiSet) -> String {
+        utf8_percent_encode(&self.value, encoding).to_string()
+    }
+
+    // RFC 5849, section 3.4.1.3.2: every pair gets encoded and joined with
+    // '=', then the pairs get joined with '&'. sort them first!
     pub fn join(params: Vec<Self>) -> String {
+        Self::join_with(params, super::TWITTER_ENCODING)
+    }
+
+    pub fn join_with(params: Vec<Self>, encoding: &'static AsciiSet) -> String {
         log::debug!("Joining params now: {:?}", params);
 
         params
             .iter()
-            .map(|p| {
-                let key = utf8_percent_encode(&p.key, super::TWITTER_ENCODING).to_string();
-                let val = utf8_percent_encode(&p.value, super::TWITTER_ENCODING).to_string();
-
-                String::new() + key.as_str() + "=" + val.as_str()
-            })
+            .map(|p| p.encoded_key_with(encoding) + "=" + p.encoded_value_with(encoding).as_str())
             .collect::<Vec<String>>()
             .join("&")
     }
 }
 
+// so a stray {:?} doesn't log a token
+impl fmt::Debug for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parameter")
+            .field("key", &self.key)
+            .field("value", &super::redact::param(&self.key, &self.value))
+            .finish()
+    }
+}
+
 impl Ord for Parameter {
     fn cmp(&self, other: &Self) -> Ordering {
-        let s = vec![self.key.clone(), self.value.clone()].join("=");
-        let o = vec![other.key.clone(), other.value.clone()].join("=");
-
-        s.cmp(&o)
+        // comparing the raw "key=value" strings isn't the same thing: the
+        // '=' ends up compared against the rest of a longer key, and '%'
+        // (how anything reserved or non-ascii starts once it's encoded)
+        // sorts before the digits and letters
+        self.encoded_key()
+            .cmp(&other.encoded_key())
+            .then_with(|| self.encoded_value().cmp(&other.encoded_value()))
     }
 }
 
@@ -51,11 +106,117 @@ impl PartialOrd for Parameter {
     }
 }
 
-impl PartialEq for Parameter {
-    fn eq(&self, other: &Self) -> bool {
-        let s = vec![self.key.clone(), self.value.clone()].join("=");
-        let o = vec![other.key.clone(), other.value.clone()].join("=");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // written separately from the percent_encoding set on purpose, straight
+    // from RFC 5849, section 3.6
+    fn spec_encode(s: &str) -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    fn pairs() -> impl Strategy<Value = Vec<(String, String)>> {
+        // small alphabets so keys actually collide, plus the characters that
+        // used to sort wrong
+        let text = "[a-cA-C0-2=_~.%+& é☃-]{0,4}";
+        prop::collection::vec((text, text), 0..12)
+    }
+
+    #[test]
+    fn test_rfc_ordering() {
+        // RFC 5849, section 3.4.1.3.2
+        let mut params = vec![
+            Parameter::new("b5", "=%3D"),
+            Parameter::new("a3", "a"),
+            Parameter::new("c@", ""),
+            Parameter::new("a2", "r b"),
+            Parameter::new("oauth_consumer_key", "9djdj82h48djs9d2"),
+            Parameter::new("oauth_token", "kkk9d7dh3k39sjv7"),
+            Parameter::new("oauth_signature_method", "HMAC-SHA1"),
+            Parameter::new("oauth_timestamp", "137131201"),
+            Parameter::new("oauth_nonce", "7d8f3e4a"),
+            Parameter::new("c2", ""),
+            Parameter::new("a3", "2 q"),
+        ];
+        params.sort();
+
+        assert_eq!(
+            Parameter::join(params),
+            "a2=r%20b&a3=2%20q&a3=a&b5=%3D%253D&c%40=&c2=&oauth_consumer_key=9djdj82h48djs9d2&oauth_nonce=7d8f3e4a&oauth_signature_method=HMAC-SHA1&oauth_timestamp=137131201&oauth_token=kkk9d7dh3k39sjv7"
+        );
+    }
 
-        s == o
+    #[test]
+    fn test_encoded_key_order() {
+        // every one of these sorted the other way around when the raw
+        // "key=value" strings were compared
+        assert!(Parameter::new("a", "x") < Parameter::new("a-b", "y"));
+        assert!(Parameter::new("k", "z") < Parameter::new("k=a", ""));
+        assert!(Parameter::new("c@", "") < Parameter::new("c2", ""));
+        assert!(Parameter::new("ké", "") < Parameter::new("k0", ""));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_sorts_by_encoded_key_then_value(pairs in pairs()) {
+            let mut params = pairs
+                .iter()
+                .map(|(k, v)| Parameter::new(k.as_str(), v.as_str()))
+                .collect::<Vec<Parameter>>();
+            params.sort();
+
+            let mut expected = pairs
+                .iter()
+                .map(|(k, v)| (spec_encode(k), spec_encode(v)))
+                .collect::<Vec<(String, String)>>();
+            expected.sort();
+
+            let actual = params
+                .iter()
+                .map(|p| (p.encoded_key(), p.encoded_value()))
+                .collect::<Vec<(String, String)>>();
+            prop_assert_eq!(actual, expected);
+        }
+
+        #[test]
+        fn prop_join_keeps_duplicates(pairs in pairs()) {
+            let mut params = pairs
+                .iter()
+                .map(|(k, v)| Parameter::new(k.as_str(), v.as_str()))
+                .collect::<Vec<Parameter>>();
+            params.sort();
+            let joined = Parameter::join(params.clone());
+
+            if params.is_empty() {
+                prop_assert_eq!(joined, "");
+            } else {
+                prop_assert_eq!(joined.split('&').count(), params.len());
+                for (segment, param) in joined.split('&').zip(params.iter()) {
+                    prop_assert_eq!(
+                        segment.to_string(),
+                        spec_encode(param.key()) + "=" + &spec_encode(param.value())
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn prop_ordering_agrees_with_equality(a in pairs(), b in pairs()) {
+            for ((ak, av), (bk, bv)) in a.iter().zip(b.iter()) {
+                let x = Parameter::new(ak.as_str(), av.as_str());
+                let y = Parameter::new(bk.as_str(), bv.as_str());
+                prop_assert_eq!(x.cmp(&y) == Ordering::Equal, x == y);
+                prop_assert_eq!(x.cmp(&y), y.cmp(&x).reverse());
+            }
+        }
     }
 }
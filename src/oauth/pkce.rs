@@ -0,0 +1,850 @@
+// OAuth 2.0 user context: the authorization code flow with PKCE, which is
+// what the v2 API wants for anything that acts as the account. the user
+// authorizes in a browser, twitter redirects to a listener on localhost, and
+// the code gets traded for an access token and a refresh token. twitter
+// rotates refresh tokens, so every refresh gets saved right away.
+
+use super::redact;
+use super::sources::{Clock, SystemClock};
+use super::{AsyncAuthenticator, Authenticator};
+use log::{debug, info};
+use oauth2::{
+    basic::{BasicClient, BasicTokenResponse},
+    reqwest::{async_http_client, http_client},
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, RefreshToken, Scope, TokenResponse, TokenUrl,
+};
+use reqwest::{
+    blocking::{Client, Request, Response},
+    header::*,
+    StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+pub const AUTHORIZE_URL: &str = "https://twitter.com/i/oauth2/authorize";
+pub const TOKEN_URL: &str = "https://api.twitter.com/2/oauth2/token";
+/// Has to be added to the app's callback URLs in the developer portal.
+pub const DEFAULT_REDIRECT_URL: &str = "http://127.0.0.1:8765/callback";
+
+/// Enough to post and upload as the account, and to keep refreshing.
+pub const DEFAULT_SCOPES: &[&str] = &["tweet.read", "tweet.write", "users.read", "offline.access"];
+
+/// Access tokens get refreshed this long before they actually expire.
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Unix time the access token stops working, if the provider said.
+    pub expires_at: Option<u64>,
+}
+
+impl fmt::Debug for UserTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserTokens")
+            .field("access_token", &redact::MASK)
+            .field(
+                "refresh_token",
+                &self.refresh_token.as_ref().map(|_| redact::MASK),
+            )
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PkceError {
+    /// Nothing stored yet, someone has to go through `authorize` first.
+    NotAuthorized,
+    /// The access token expired and there's no refresh token to get a new
+    /// one with (offline.access wasn't granted).
+    NoRefreshToken,
+    /// The user said no, or the provider had some other complaint.
+    Denied(String),
+    StateMismatch,
+    MissingCode,
+}
+
+impl fmt::Display for PkceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PkceError::NotAuthorized => write!(f, "no stored tokens, authorize first"),
+            PkceError::NoRefreshToken => {
+                write!(f, "access token expired and there's no refresh token")
+            }
+            PkceError::Denied(error) => write!(f, "authorization failed: {}", error),
+            PkceError::StateMismatch => write!(f, "callback state does not match the request"),
+            PkceError::MissingCode => write!(f, "callback is missing the authorization code"),
+        }
+    }
+}
+
+impl std::error::Error for PkceError {}
+
+/// Where tokens live between runs.
+pub trait TokenStore: Send + Sync {
+    fn load(&self) -> Result<Option<UserTokens>, SyncError>;
+    fn save(&self, tokens: &UserTokens) -> Result<(), SyncError>;
+}
+
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<Option<UserTokens>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self) -> Result<Option<UserTokens>, SyncError> {
+        Ok(self.tokens.lock().unwrap().clone())
+    }
+
+    fn save(&self, tokens: &UserTokens) -> Result<(), SyncError> {
+        *self.tokens.lock().unwrap() = Some(tokens.clone());
+        Ok(())
+    }
+}
+
+/// Keeps the tokens in a JSON file, readable only by the owner.
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileTokenStore { path: path.into() }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> Result<Option<UserTokens>, SyncError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    fn save(&self, tokens: &UserTokens) -> Result<(), SyncError> {
+        // write next to it and rename, so a crash halfway through doesn't
+        // lose the only copy of a refresh token that's already been rotated
+        let tmp = self.path.with_extension("tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(tokens)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// What has to be kept between sending the user off and them coming back.
+pub struct PendingAuthorization {
+    pub url: Url,
+    state: String,
+    verifier: String,
+}
+
+pub struct PkceClient {
+    client_id: String,
+    client_secret: Option<String>,
+    authorize_url: String,
+    token_url: String,
+    redirect_url: String,
+    scopes: Vec<String>,
+    store: Box<dyn TokenStore>,
+    clock: Box<dyn Clock>,
+    tokens: Mutex<Option<UserTokens>>,
+    // `tokens` can't be held across an await, so async refreshes take turns
+    // on this instead
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl PkceClient {
+    /// A public client, i.e. no client secret. `redirect_url` has to match
+    /// one of the callback URLs set up for the app exactly.
+    pub fn new(client_id: String, redirect_url: String) -> Self {
+        PkceClient {
+            client_id,
+            client_secret: None,
+            authorize_url: AUTHORIZE_URL.to_string(),
+            token_url: TOKEN_URL.to_string(),
+            redirect_url,
+            scopes: DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect(),
+            store: Box::new(MemoryTokenStore::new()),
+            clock: Box::new(SystemClock),
+            tokens: Mutex::new(None),
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// For confidential clients, which authenticate to the token endpoint.
+    pub fn with_client_secret(mut self, secret: String) -> Self {
+        self.client_secret = Some(secret);
+        self
+    }
+
+    pub fn with_endpoints(mut self, authorize_url: &str, token_url: &str) -> Self {
+        self.authorize_url = authorize_url.to_string();
+        self.token_url = token_url.to_string();
+        self
+    }
+
+    pub fn with_scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_store(mut self, store: impl TokenStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    fn oauth2_client(&self) -> Result<BasicClient, SyncError> {
+        Ok(BasicClient::new(
+            ClientId::new(self.client_id.clone()),
+            self.client_secret.clone().map(ClientSecret::new),
+            AuthUrl::new(self.authorize_url.clone())?,
+            Some(TokenUrl::new(self.token_url.clone())?),
+        )
+        .set_redirect_uri(RedirectUrl::new(self.redirect_url.clone())?))
+    }
+
+    /// Makes the URL to send the user to, with a fresh PKCE challenge and
+    /// state.
+    pub fn start_authorization(&self) -> Result<PendingAuthorization, SyncError> {
+        let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, state) = self
+            .oauth2_client()?
+            .authorize_url(CsrfToken::new_random)
+            .add_scopes(self.scopes.iter().cloned().map(Scope::new))
+            .set_pkce_challenge(challenge)
+            .url();
+
+        Ok(PendingAuthorization {
+            url,
+            state: state.secret().clone(),
+            verifier: verifier.secret().clone(),
+        })
+    }
+
+    /// Trades the code from the callback for tokens, and stores them.
+    pub fn finish_authorization(
+        &self,
+        pending: PendingAuthorization,
+        code: &str,
+    ) -> Result<UserTokens, SyncError> {
+        let response = self
+            .oauth2_client()?
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .set_pkce_verifier(PkceCodeVerifier::new(pending.verifier))
+            .request(http_client)?;
+
+        let mut tokens = self.tokens.lock().unwrap();
+        let fresh = self.keep(&response, None)?;
+        *tokens = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    /// The whole flow in one go: listens on the redirect URL, hands the
+    /// authorization URL to `open` (print it, open a browser, ...) and waits
+    /// for the user to come back.
+    pub fn authorize<F>(&self, open: F) -> Result<UserTokens, SyncError>
+    where
+        F: FnOnce(&Url),
+    {
+        // bind before the user can possibly get redirected
+        let listener = Loopback::bind(&self.redirect_url)?;
+        let pending = self.start_authorization()?;
+
+        open(&pending.url);
+        let code = listener.wait(&pending.state)?;
+
+        self.finish_authorization(pending, &code)
+    }
+
+    /// A usable access token, refreshing it first if it's about to expire.
+    pub fn access_token(&self) -> Result<String, SyncError> {
+        // held across the refresh: with rotation, two refreshes racing each
+        // other would leave one of them holding a dead refresh token
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens.is_none() {
+            *tokens = self.store.load()?;
+        }
+
+        let current = tokens.as_ref().ok_or(PkceError::NotAuthorized)?;
+        if self.expired(current) {
+            let fresh = self.refresh_locked(current)?;
+            *tokens = Some(fresh);
+        }
+
+        Ok(tokens.as_ref().unwrap().access_token.clone())
+    }
+
+    /// `access_token`, for the async client.
+    pub async fn access_token_async(&self) -> Result<String, SyncError> {
+        let _refreshing = self.refreshing.lock().await;
+        let current = self.current()?;
+        if !self.expired(&current) {
+            return Ok(current.access_token);
+        }
+
+        Ok(self.refresh_current_async(&current).await?.access_token)
+    }
+
+    /// Gets a new access token (and refresh token) right now.
+    pub fn refresh(&self) -> Result<UserTokens, SyncError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens.is_none() {
+            *tokens = self.store.load()?;
+        }
+
+        let current = tokens.as_ref().ok_or(PkceError::NotAuthorized)?;
+        let fresh = self.refresh_locked(current)?;
+        *tokens = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    /// `refresh`, for the async client.
+    pub async fn refresh_async(&self) -> Result<UserTokens, SyncError> {
+        let _refreshing = self.refreshing.lock().await;
+        let current = self.current()?;
+        self.refresh_current_async(&current).await
+    }
+
+    fn current(&self) -> Result<UserTokens, SyncError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens.is_none() {
+            *tokens = self.store.load()?;
+        }
+
+        Ok(tokens.clone().ok_or(PkceError::NotAuthorized)?)
+    }
+
+    fn expired(&self, tokens: &UserTokens) -> bool {
+        tokens
+            .expires_at
+            .map(|at| at <= self.clock.now() + EXPIRY_MARGIN_SECS)
+            .unwrap_or(false)
+    }
+
+    async fn refresh_current_async(&self, current: &UserTokens) -> Result<UserTokens, SyncError> {
+        let refresh_token = current
+            .refresh_token
+            .as_ref()
+            .ok_or(PkceError::NoRefreshToken)?;
+
+        info!("Refreshing the OAuth 2.0 access token");
+        let response = self
+            .oauth2_client()?
+            .exchange_refresh_token(&RefreshToken::new(refresh_token.clone()))
+            .request_async(async_http_client)
+            .await?;
+
+        let fresh = self.keep(&response, Some(refresh_token))?;
+        *self.tokens.lock().unwrap() = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    fn refresh_locked(&self, current: &UserTokens) -> Result<UserTokens, SyncError> {
+        let refresh_token = current
+            .refresh_token
+            .as_ref()
+            .ok_or(PkceError::NoRefreshToken)?;
+
+        info!("Refreshing the OAuth 2.0 access token");
+        let response = self
+            .oauth2_client()?
+            .exchange_refresh_token(&RefreshToken::new(refresh_token.clone()))
+            .request(http_client)?;
+
+        self.keep(&response, Some(refresh_token))
+    }
+
+    /// Turns a token response into `UserTokens` and saves them.
+    fn keep(
+        &self,
+        response: &BasicTokenResponse,
+        previous_refresh: Option<&String>,
+    ) -> Result<UserTokens, SyncError> {
+        let tokens = UserTokens {
+            access_token: response.access_token().secret().clone(),
+            // providers that don't rotate leave it out, the old one still works
+            refresh_token: response
+                .refresh_token()
+                .map(|t| t.secret().clone())
+                .or_else(|| previous_refresh.cloned()),
+            expires_at: response
+                .expires_in()
+                .map(|d| self.clock.now() + d.as_secs()),
+        };
+        debug!("New access token expires at {:?}", tokens.expires_at);
+
+        self.store.save(&tokens)?;
+        Ok(tokens)
+    }
+}
+
+impl Authenticator for PkceClient {
+    fn auth_request(&self, mut request: Request) -> Result<Request, SyncError> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", self.access_token()?))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+
+        Ok(request)
+    }
+
+    /// Sends the request, and if the token got revoked before it expired,
+    /// refreshes and tries once more.
+    fn execute(&self, http: &Client, request: Request) -> Result<Response, SyncError> {
+        let retry = request.try_clone();
+        let response = http.execute(self.auth_request(request)?)?;
+
+        match retry {
+            Some(request) if response.status() == StatusCode::UNAUTHORIZED => {
+                self.refresh()?;
+                Ok(http.execute(self.auth_request(request)?)?)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncAuthenticator for PkceClient {
+    async fn auth_request_async(
+        &self,
+        mut request: reqwest::Request,
+    ) -> Result<reqwest::Request, SyncError> {
+        let value = HeaderValue::from_str(&format!("Bearer {}", self.access_token_async().await?))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+
+        Ok(request)
+    }
+
+    async fn execute_async(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, SyncError> {
+        let retry = request.try_clone();
+        let response = http
+            .execute(self.auth_request_async(request).await?)
+            .await?;
+
+        match retry {
+            Some(request) if response.status() == StatusCode::UNAUTHORIZED => {
+                self.refresh_async().await?;
+                Ok(http
+                    .execute(self.auth_request_async(request).await?)
+                    .await?)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Listens on the redirect URL for the provider sending the user back.
+pub struct Loopback {
+    listener: TcpListener,
+    path: String,
+}
+
+impl Loopback {
+    /// Binds to the host and port of the redirect URL, which should be
+    /// something like http://127.0.0.1:8765/callback.
+    pub fn bind(redirect_url: &str) -> Result<Self, SyncError> {
+        let url = Url::parse(redirect_url)?;
+        let host = url.host_str().unwrap_or("127.0.0.1");
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        Ok(Loopback {
+            listener: TcpListener::bind((host, port))?,
+            path: url.path().to_string(),
+        })
+    }
+
+    /// Waits for the callback and returns the authorization code in it.
+    /// Anything else that shows up (favicons and such) gets a 404.
+    pub fn wait(&self, state: &str) -> Result<String, SyncError> {
+        for stream in self.listener.incoming() {
+            let mut stream = stream?;
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line)?;
+
+            let target = line.split_whitespace().nth(1).unwrap_or("/");
+            let url = Url::parse("http://localhost")?.join(target)?;
+            if url.path() != self.path {
+                stream.write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )?;
+                continue;
+            }
+
+            let result = Self::code_from(&url, state);
+            let body = match result {
+                Ok(_) => "Authorized! You can close this window now.".to_string(),
+                Err(ref e) => format!("Authorization failed: {}", e),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+
+            return Ok(result?);
+        }
+
+        Err(Box::new(PkceError::MissingCode))
+    }
+
+    fn code_from(callback: &Url, state: &str) -> Result<String, PkceError> {
+        let field = |key: &str| {
+            callback
+                .query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        if let Some(error) = field("error") {
+            return Err(PkceError::Denied(error));
+        }
+        if field("state").as_deref() != Some(state) {
+            return Err(PkceError::StateMismatch);
+        }
+
+        field("code").ok_or(PkceError::MissingCode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sources::FixedClock;
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn form_field(body: &str, key: &str) -> Option<String> {
+        url::form_urlencoded::parse(body.as_bytes())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    // a token endpoint that rotates refresh tokens: code -> access-1 and
+    // refresh-1, refresh-n -> access-(n+1) and refresh-(n+1)
+    fn token_server() -> MockServer {
+        let issued = AtomicUsize::new(0);
+
+        MockServer::start(move |request| {
+            let body = request.body_text();
+            let valid = match form_field(&body, "grant_type").as_deref() {
+                Some("authorization_code") => {
+                    form_field(&body, "code").as_deref() == Some("the-code")
+                        && form_field(&body, "code_verifier").is_some()
+                }
+                Some("refresh_token") => {
+                    form_field(&body, "refresh_token")
+                        == Some(format!("refresh-{}", issued.load(Ordering::SeqCst)))
+                }
+                _ => false,
+            };
+            if !valid {
+                return MockResponse::new(400, r#"{"error":"invalid_request"}"#)
+                    .with_header("Content-Type", "application/json");
+            }
+
+            let n = issued.fetch_add(1, Ordering::SeqCst) + 1;
+            MockResponse::new(
+                200,
+                format!(
+                    r#"{{"token_type":"bearer","expires_in":7200,"access_token":"access-{}","scope":"tweet.write offline.access","refresh_token":"refresh-{}"}}"#,
+                    n, n
+                ),
+            )
+            .with_header("Content-Type", "application/json")
+        })
+    }
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    fn client(server: &MockServer, redirect_url: &str) -> PkceClient {
+        PkceClient::new("client-id".to_string(), redirect_url.to_string())
+            .with_endpoints(
+                "https://twitter.com/i/oauth2/authorize",
+                &server.url("/token"),
+            )
+            .with_clock(FixedClock(1_000_000))
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let server = token_server();
+        let client = client(&server, "http://127.0.0.1:8765/callback");
+        let pending = client.start_authorization().unwrap();
+
+        let field = |key: &str| {
+            pending
+                .url
+                .query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+        };
+        assert_eq!(field("response_type").as_deref(), Some("code"));
+        assert_eq!(field("client_id").as_deref(), Some("client-id"));
+        assert_eq!(
+            field("redirect_uri").as_deref(),
+            Some("http://127.0.0.1:8765/callback")
+        );
+        assert_eq!(
+            field("scope").as_deref(),
+            Some("tweet.read tweet.write users.read offline.access")
+        );
+        assert_eq!(field("code_challenge_method").as_deref(), Some("S256"));
+        assert!(field("code_challenge").is_some());
+        assert_eq!(field("state"), Some(pending.state.clone()));
+    }
+
+    #[test]
+    fn test_loopback_flow() {
+        let server = token_server();
+        let redirect = format!("http://127.0.0.1:{}/callback", free_port());
+        let client = client(&server, &redirect);
+
+        // plays the part of the browser getting redirected back
+        let tokens = client
+            .authorize(|url| {
+                let state = url
+                    .query_pairs()
+                    .find(|(k, _)| k == "state")
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap();
+                let redirect = redirect.clone();
+                std::thread::spawn(move || {
+                    let http = Client::new();
+                    http.get(format!("{}/../favicon.ico", redirect))
+                        .send()
+                        .unwrap();
+                    http.get(format!("{}?state={}&code=the-code", redirect, state))
+                        .send()
+                        .unwrap();
+                });
+            })
+            .unwrap();
+
+        assert_eq!(
+            tokens,
+            UserTokens {
+                access_token: "access-1".to_string(),
+                refresh_token: Some("refresh-1".to_string()),
+                expires_at: Some(1_000_000 + 7200),
+            }
+        );
+        assert_eq!(client.access_token().unwrap(), "access-1");
+    }
+
+    #[test]
+    fn test_callback_errors() {
+        let callback =
+            |query: &str| Url::parse(&format!("http://127.0.0.1/callback?{}", query)).unwrap();
+
+        assert_eq!(
+            Loopback::code_from(&callback("state=abc&code=xyz"), "abc"),
+            Ok("xyz".to_string())
+        );
+        assert_eq!(
+            Loopback::code_from(&callback("state=evil&code=xyz"), "abc"),
+            Err(PkceError::StateMismatch)
+        );
+        assert_eq!(
+            Loopback::code_from(&callback("error=access_denied&state=abc"), "abc"),
+            Err(PkceError::Denied("access_denied".to_string()))
+        );
+        assert_eq!(
+            Loopback::code_from(&callback("state=abc"), "abc"),
+            Err(PkceError::MissingCode)
+        );
+    }
+
+    // lets a test look at what got saved after handing the store over
+    #[derive(Clone, Default)]
+    struct SharedStore(Arc<MemoryTokenStore>);
+
+    impl TokenStore for SharedStore {
+        fn load(&self) -> Result<Option<UserTokens>, SyncError> {
+            self.0.load()
+        }
+
+        fn save(&self, tokens: &UserTokens) -> Result<(), SyncError> {
+            self.0.save(tokens)
+        }
+    }
+
+    #[test]
+    fn test_refresh_rotation() {
+        let server = token_server();
+        let store = SharedStore::default();
+        let client = client(&server, "http://127.0.0.1:8765/callback").with_store(store.clone());
+
+        let pending = client.start_authorization().unwrap();
+        client.finish_authorization(pending, "the-code").unwrap();
+
+        // still good, no refresh
+        assert_eq!(client.access_token().unwrap(), "access-1");
+
+        // both the new access token and the rotated refresh token get saved
+        client.refresh().unwrap();
+        client.refresh().unwrap();
+        assert_eq!(
+            store.load().unwrap().unwrap().refresh_token.as_deref(),
+            Some("refresh-3")
+        );
+        assert_eq!(client.access_token().unwrap(), "access-3");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_expired_token_is_refreshed() {
+        let server = token_server();
+        let store = MemoryTokenStore::new();
+        store
+            .save(&UserTokens {
+                access_token: "access-0".to_string(),
+                refresh_token: Some("refresh-0".to_string()),
+                expires_at: Some(1_000_000 + 30),
+            })
+            .unwrap();
+        let client = client(&server, "http://127.0.0.1:8765/callback").with_store(store);
+
+        // inside the margin counts as expired
+        assert_eq!(client.access_token().unwrap(), "access-1");
+    }
+
+    #[test]
+    fn test_not_authorized() {
+        let server = token_server();
+        let client = client(&server, "http://127.0.0.1:8765/callback");
+
+        let err = client.access_token().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PkceError>(),
+            Some(&PkceError::NotAuthorized)
+        );
+
+        let client = self::client(&server, "http://127.0.0.1:8765/callback").with_store({
+            let store = MemoryTokenStore::new();
+            store
+                .save(&UserTokens {
+                    access_token: "old".to_string(),
+                    refresh_token: None,
+                    expires_at: Some(0),
+                })
+                .unwrap();
+            store
+        });
+        let err = client.access_token().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PkceError>(),
+            Some(&PkceError::NoRefreshToken)
+        );
+    }
+
+    #[test]
+    fn test_file_token_store() {
+        let path =
+            std::env::temp_dir().join(format!("fox-friday-tokens-{}.json", std::process::id()));
+        let store = FileTokenStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+
+        let tokens = UserTokens {
+            access_token: "access".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Some(1234),
+        };
+        store.save(&tokens).unwrap();
+        assert_eq!(FileTokenStore::new(&path).load().unwrap(), Some(tokens));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_revoked_token_is_refreshed() {
+        let server = token_server();
+        let api = MockServer::start(|request| {
+            if request.header("authorization") == Some("Bearer access-2") {
+                MockResponse::new(200, "{}")
+            } else {
+                MockResponse::new(401, "")
+            }
+        });
+        let client = client(&server, "http://127.0.0.1:8765/callback");
+        let pending = client.start_authorization().unwrap();
+        client.finish_authorization(pending, "the-code").unwrap();
+
+        let http = Client::new();
+        let response = client
+            .execute(&http, http.get(api.url("/2/users/me")).build().unwrap())
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(api.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_async_refresh() {
+        let server = token_server();
+        let store = MemoryTokenStore::new();
+        store
+            .save(&UserTokens {
+                access_token: "access-0".to_string(),
+                refresh_token: Some("refresh-0".to_string()),
+                expires_at: Some(1_000_000 + 30),
+            })
+            .unwrap();
+        let client = client(&server, "http://127.0.0.1:8765/callback").with_store(store);
+
+        // racing each other, only one of them should refresh
+        let (a, b) = tokio::join!(client.access_token_async(), client.access_token_async());
+        assert_eq!(a.unwrap(), "access-1");
+        assert_eq!(b.unwrap(), "access-1");
+        assert_eq!(server.requests().len(), 1);
+    }
+}
@@ -0,0 +1,81 @@
+// everything about an OAuth 1.0a service that isn't the same everywhere:
+// where its token endpoints are, whether it wants a realm, if it's two-legged,
+// and (for the odd one out) how it percent-encodes.
+
+use super::TWITTER_ENCODING;
+use percent_encoding::AsciiSet;
+
+#[derive(Clone, Debug)]
+pub struct Provider {
+    /// What goes through percent-encoding untouched. RFC 5849's set, which
+    /// is what Twitter uses, unless a provider does its own thing.
+    pub encoding: &'static AsciiSet,
+    /// Whether requests have to be signed with a user token (three-legged),
+    /// or can be made with just the consumer credentials (two-legged).
+    pub requires_token: bool,
+    /// Sent as the header's realm, if set. Never signed.
+    pub realm: Option<String>,
+    pub request_token_url: String,
+    pub authorize_url: String,
+    pub access_token_url: String,
+}
+
+impl Provider {
+    /// A three-legged RFC 5849 provider with these endpoints.
+    pub fn new(request_token_url: &str, authorize_url: &str, access_token_url: &str) -> Self {
+        Provider {
+            encoding: TWITTER_ENCODING,
+            requires_token: true,
+            realm: None,
+            request_token_url: request_token_url.to_string(),
+            authorize_url: authorize_url.to_string(),
+            access_token_url: access_token_url.to_string(),
+        }
+    }
+
+    pub fn twitter() -> Self {
+        Self::new(
+            "https://api.twitter.com/oauth/request_token",
+            "https://api.twitter.com/oauth/authorize",
+            "https://api.twitter.com/oauth/access_token",
+        )
+    }
+
+    pub fn tumblr() -> Self {
+        Self::new(
+            "https://www.tumblr.com/oauth/request_token",
+            "https://www.tumblr.com/oauth/authorize",
+            "https://www.tumblr.com/oauth/access_token",
+        )
+    }
+
+    pub fn flickr() -> Self {
+        Self::new(
+            "https://www.flickr.com/services/oauth/request_token",
+            "https://www.flickr.com/services/oauth/authorize",
+            "https://www.flickr.com/services/oauth/access_token",
+        )
+    }
+
+    pub fn with_encoding(mut self, encoding: &'static AsciiSet) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn with_realm(mut self, realm: &str) -> Self {
+        self.realm = Some(realm.to_string());
+        self
+    }
+
+    /// Requests only need the consumer credentials.
+    pub fn two_legged(mut self) -> Self {
+        self.requires_token = false;
+        self
+    }
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Self::twitter()
+    }
+}
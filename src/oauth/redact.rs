@@ -0,0 +1,325 @@
+// keeps secrets out of the logs. anything that could be used to sign or
+// replay a request (keys, tokens, signatures, nonces, verifiers) gets masked
+// before it's formatted, so turning up RUST_LOG is safe.
+
+use super::TWITTER_ENCODING;
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use reqwest::{header::HeaderMap, Method, Url};
+use std::fmt;
+
+pub const MASK: &str = "[redacted]";
+
+// parameter names, in headers, queries, forms and base strings
+const SENSITIVE_PARAMS: &[&str] = &[
+    "oauth_consumer_key",
+    "oauth_token",
+    "oauth_signature",
+    "oauth_nonce",
+    "oauth_verifier",
+    "access_token",
+    "refresh_token",
+    "client_secret",
+    "code",
+    "code_verifier",
+];
+
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-verify-credentials-authorization",
+    "x-aws-parameters-secrets-token",
+];
+
+pub fn is_sensitive(key: &str) -> bool {
+    SENSITIVE_PARAMS.contains(&key)
+}
+
+/// `value`, unless `key` is something we don't log.
+pub fn param<'a>(key: &str, value: &'a str) -> &'a str {
+    if is_sensitive(key) {
+        MASK
+    } else {
+        value
+    }
+}
+
+/// An Authorization header with its credentials masked. OAuth headers keep
+/// the fields that aren't secret; anything else is just the scheme.
+pub fn authorization(value: &str) -> String {
+    match value.split_once(' ') {
+        Some((scheme, fields)) if scheme.eq_ignore_ascii_case("OAuth") => {
+            let fields: Vec<String> = fields
+                .split(',')
+                .map(|field| match field.split_once('=') {
+                    Some((key, _)) if is_sensitive(key.trim()) => {
+                        format!("{}=\"{}\"", key, MASK)
+                    }
+                    _ => field.to_string(),
+                })
+                .collect();
+            format!("{} {}", scheme, fields.join(","))
+        }
+        Some((scheme, _)) => format!("{} {}", scheme, MASK),
+        None => MASK.to_string(),
+    }
+}
+
+/// A signature base string with the secret parameters masked. It's still
+/// a valid base string, just not the one that was signed.
+pub fn base_string(base_string: &str) -> String {
+    let mut parts = base_string.splitn(3, '&');
+    let (method, url, params) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(url), Some(params)) => (method, url, params),
+        _ => return MASK.to_string(),
+    };
+
+    // the parameters were encoded once on their own, then once more as a
+    // whole; only undo the second one
+    let params = percent_decode_str(params).decode_utf8_lossy();
+    let params: Vec<String> = params
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_sensitive(&percent_decode_str(key).decode_utf8_lossy()) => {
+                format!("{}={}", key, MASK)
+            }
+            _ => pair.to_string(),
+        })
+        .collect();
+
+    format!(
+        "{}&{}&{}",
+        method,
+        url,
+        utf8_percent_encode(&params.join("&"), TWITTER_ENCODING)
+    )
+}
+
+pub fn url(url: &Url) -> Url {
+    if !url.query_pairs().any(|(k, _)| is_sensitive(&k)) {
+        return url.clone();
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (k.to_string(), param(&k, &v).to_string()))
+        .collect();
+    let mut redacted = url.clone();
+    redacted.query_pairs_mut().clear().extend_pairs(pairs);
+    redacted
+}
+
+/// A request's method, url and headers, for logging. Works for the
+/// blocking and async requests alike. Bodies are left out entirely.
+pub struct Request<'a> {
+    method: &'a Method,
+    url: &'a Url,
+    headers: &'a HeaderMap,
+}
+
+pub fn request<'a>(method: &'a Method, url: &'a Url, headers: &'a HeaderMap) -> Request<'a> {
+    Request {
+        method,
+        url,
+        headers,
+    }
+}
+
+impl fmt::Display for Request<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", self.method, url(self.url))?;
+
+        let mut headers = f.debug_map();
+        for (name, value) in self.headers.iter() {
+            let value = value.to_str().unwrap_or("<binary>");
+            if !SENSITIVE_HEADERS.contains(&name.as_str()) {
+                headers.entry(&name.as_str(), &value);
+            } else if name.as_str().ends_with("authorization") {
+                headers.entry(&name.as_str(), &authorization(value));
+            } else {
+                headers.entry(&name.as_str(), &MASK);
+            }
+        }
+        headers.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::bearer::BearerClient;
+    use super::super::client::{AccessToken, OAuthClient};
+    use super::super::header::OAuthHeader;
+    use super::super::parameter::Parameter;
+    use super::super::sources::{FixedClock, FixedNonce};
+    use super::super::Authenticator;
+    use super::*;
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use reqwest::blocking::Client;
+    use std::sync::Mutex;
+    use std::thread::{self, ThreadId};
+
+    // everything logged, by the thread that logged it, so tests running
+    // alongside each other don't see each other's lines
+    struct Capture(Mutex<Vec<(ThreadId, String)>>);
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    impl Log for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let line = format!("{} {}", record.level(), record.args());
+            self.0.lock().unwrap().push((thread::current().id(), line));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture(f: impl FnOnce()) -> String {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Trace);
+
+        f();
+
+        let id = thread::current().id();
+        CAPTURE
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(t, _)| *t == id)
+            .map(|(_, line)| line.clone())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    const SECRETS: &[&str] = &[
+        "consumerkey4a9b",
+        "consumersecret7c2d",
+        "usertoken5e8f",
+        "usersecret1b3c",
+        "noncevalue9d0e",
+    ];
+
+    #[test]
+    fn test_signing_logs_no_secrets() {
+        let oauth = OAuthClient::new(
+            SECRETS[0].to_string(),
+            SECRETS[1].to_string(),
+            SECRETS[2].to_string(),
+            SECRETS[3].to_string(),
+        )
+        .with_clock(FixedClock(1318622958))
+        .with_nonce_source(FixedNonce(SECRETS[4].to_string()));
+
+        let mut signature = String::new();
+        let logs = capture(|| {
+            let request = Client::new()
+                .post("https://api.twitter.com/1.1/statuses/update.json?include_entities=true")
+                .form(&[("status", "it's fox friday!")])
+                .build()
+                .unwrap();
+            let request = oauth.auth_request(request).unwrap();
+
+            let header = request.headers()["authorization"].to_str().unwrap();
+            signature = header.parse::<OAuthHeader>().unwrap().signature;
+        });
+
+        assert!(logs.contains("Final request"));
+        assert!(logs.contains("Base string"));
+        assert!(logs.contains(MASK));
+        for secret in SECRETS.iter().chain(std::iter::once(&signature.as_str())) {
+            assert!(
+                !logs.contains(secret),
+                "{} is in the logs:\n{}",
+                secret,
+                logs
+            );
+        }
+        // the signature's encoded in the header, so check that too
+        let encoded = utf8_percent_encode(&signature, TWITTER_ENCODING).to_string();
+        assert!(!logs.contains(&encoded));
+    }
+
+    #[test]
+    fn test_bearer_logs_no_secrets() {
+        let bearer = BearerClient::new("app-key".to_string(), "app-secret".to_string())
+            .with_bearer_token(SECRETS[2].to_string());
+
+        let logs = capture(|| {
+            let request = Client::new()
+                .get("https://api.twitter.com/1.1/search/tweets.json?q=fox")
+                .build()
+                .unwrap();
+            let signed = bearer.auth_request(request).unwrap();
+            log::debug!(
+                "{}",
+                super::request(signed.method(), signed.url(), signed.headers())
+            );
+        });
+
+        assert!(logs.contains("Bearer [redacted]"));
+        assert!(!logs.contains(SECRETS[2]));
+    }
+
+    #[test]
+    fn test_debug_output_is_masked() {
+        let header = OAuthHeader {
+            consumer_key: SECRETS[0].to_string(),
+            token: Some(SECRETS[2].to_string()),
+            nonce: SECRETS[4].to_string(),
+            signature: "c2lnbmF0dXJl".to_string(),
+            ..Default::default()
+        };
+        let token = AccessToken {
+            token: SECRETS[2].to_string(),
+            secret: SECRETS[3].to_string(),
+            user_id: None,
+            screen_name: Some("foxfriday".to_string()),
+        };
+        let params = vec![
+            Parameter::new("oauth_token", SECRETS[2]),
+            Parameter::new("status", "hi"),
+        ];
+
+        let logs = capture(|| {
+            log::log!(Level::Debug, "{:?}", header);
+            log::log!(Level::Debug, "{:?}", token);
+            log::log!(Level::Debug, "{:?}", params);
+        });
+
+        assert!(logs.contains("foxfriday"));
+        assert!(logs.contains("status"));
+        for secret in SECRETS.iter().chain(std::iter::once(&"c2lnbmF0dXJl")) {
+            assert!(
+                !logs.contains(secret),
+                "{} is in the logs:\n{}",
+                secret,
+                logs
+            );
+        }
+    }
+
+    #[test]
+    fn test_redact_helpers() {
+        assert_eq!(
+            authorization(r#"OAuth oauth_consumer_key="key", oauth_signature_method="HMAC-SHA1""#),
+            r#"OAuth oauth_consumer_key="[redacted]", oauth_signature_method="HMAC-SHA1""#
+        );
+        assert_eq!(authorization("Basic YXBwOnNlY3JldA=="), "Basic [redacted]");
+
+        assert_eq!(
+            base_string("GET&https%3A%2F%2Fa.example%2F&a%3D1%26oauth_token%3Dtok%26z%3D2"),
+            "GET&https%3A%2F%2Fa.example%2F&a%3D1%26oauth_token%3D%5Bredacted%5D%26z%3D2"
+        );
+
+        let redacted = url(&Url::parse("https://a.example/cb?code=abc&state=xyz").unwrap());
+        assert_eq!(
+            redacted.as_str(),
+            "https://a.example/cb?code=%5Bredacted%5D&state=xyz"
+        );
+    }
+}
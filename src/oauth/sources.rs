@@ -0,0 +1,83 @@
+// where oauth_timestamp and oauth_nonce come from. the defaults are the wall
+// clock and random bytes, the fixed ones are for tests and for replaying a
+// request out of the logs.
+
+use super::base64;
+use rand::RngCore;
+use std::time::SystemTime;
+
+pub trait Clock: Send + Sync {
+    /// Seconds since the unix epoch.
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// Always says it's the same time.
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+pub trait NonceSource: Send + Sync {
+    fn nonce(&self) -> String;
+}
+
+/// 32 random bytes, URL-safe base64'd, so there's nothing to percent-encode.
+pub struct RandomNonce;
+
+impl NonceSource for RandomNonce {
+    fn nonce(&self) -> String {
+        let mut nonce = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        base64::encode(nonce, base64::URL_SAFE_NO_PAD)
+    }
+}
+
+/// Hands out the same nonce every time. Providers will reject the second
+/// request that uses it, so keep this to tests.
+pub struct FixedNonce(pub String);
+
+impl NonceSource for FixedNonce {
+    fn nonce(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_nonce() {
+        let a = RandomNonce.nonce();
+        let b = RandomNonce.nonce();
+
+        assert_ne!(a, b);
+        assert!(!a.is_empty());
+        assert_eq!(a.len(), 43);
+        assert!(a
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn test_fixed_sources() {
+        assert_eq!(FixedClock(1318622958).now(), 1318622958);
+        assert_eq!(FixedNonce("abc".to_string()).nonce(), "abc");
+        assert!(SystemClock.now() > 1318622958);
+    }
+}
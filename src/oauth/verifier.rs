@@ -0,0 +1,510 @@
+// the other side of OAuthClient: checking requests someone else signed, for
+// the mock twitter server and anything else that takes oauth-signed posts
+
+use super::header::{HeaderError, OAuthHeader};
+use super::parameter::Parameter;
+use super::signature::{self, HmacSha1, SignatureVerifier};
+use super::sources::{Clock, SystemClock};
+use reqwest::{Method, Url};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+/// Five minutes either way, same as most providers allow.
+pub const DEFAULT_WINDOW_SECS: u64 = 300;
+
+/// Looks up the secrets for the keys and tokens a request claims to have.
+pub trait SecretStore: Send + Sync {
+    fn consumer_secret(&self, consumer_key: &str) -> Option<String>;
+    fn token_secret(&self, consumer_key: &str, token: &str) -> Option<String>;
+}
+
+/// A fixed set of consumers and tokens, which is all a mock server needs.
+#[derive(Default)]
+pub struct StaticSecrets {
+    consumers: HashMap<String, String>,
+    tokens: HashMap<(String, String), String>,
+}
+
+impl StaticSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_consumer(mut self, key: &str, secret: &str) -> Self {
+        self.consumers.insert(key.to_string(), secret.to_string());
+        self
+    }
+
+    pub fn with_token(mut self, consumer_key: &str, token: &str, secret: &str) -> Self {
+        self.tokens.insert(
+            (consumer_key.to_string(), token.to_string()),
+            secret.to_string(),
+        );
+        self
+    }
+}
+
+impl SecretStore for StaticSecrets {
+    fn consumer_secret(&self, consumer_key: &str) -> Option<String> {
+        self.consumers.get(consumer_key).cloned()
+    }
+
+    fn token_secret(&self, consumer_key: &str, token: &str) -> Option<String> {
+        self.tokens
+            .get(&(consumer_key.to_string(), token.to_string()))
+            .cloned()
+    }
+}
+
+/// RFC 5849, section 3.3: a nonce only has to be unique for a given consumer
+/// key, token and timestamp.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Nonce {
+    pub consumer_key: String,
+    pub token: Option<String>,
+    pub timestamp: u64,
+    pub value: String,
+}
+
+pub trait NonceStore: Send + Sync {
+    /// Remembers the nonce, returning false if it's been seen before.
+    /// Anything with a timestamp before `expired_before` can be forgotten,
+    /// since the timestamp check turns those requests away anyway.
+    fn insert(&self, nonce: Nonce, expired_before: u64) -> bool;
+}
+
+/// Keeps nonces in memory, so it's only good for a single process.
+#[derive(Default)]
+pub struct MemoryNonceStore {
+    seen: Mutex<HashSet<Nonce>>,
+}
+
+impl MemoryNonceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn insert(&self, nonce: Nonce, expired_before: u64) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|n| n.timestamp >= expired_before);
+
+        seen.insert(nonce)
+    }
+}
+
+/// The parts of an incoming request that go into checking its signature.
+pub struct SignedRequest<'a> {
+    pub method: &'a Method,
+    pub url: &'a Url,
+    pub authorization: &'a str,
+    pub content_type: Option<&'a str>,
+    pub body: &'a [u8],
+}
+
+/// Who signed a request that checked out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verified {
+    pub consumer_key: String,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    Header(HeaderError),
+    UnsupportedVersion(String),
+    UnsupportedSignatureMethod(String),
+    UnknownConsumer,
+    UnknownToken,
+    BadTimestamp,
+    NonceReused,
+    BadSignature,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Header(e) => write!(f, "{}", e),
+            VerifyError::UnsupportedVersion(v) => write!(f, "unsupported oauth_version {}", v),
+            VerifyError::UnsupportedSignatureMethod(m) => {
+                write!(f, "unsupported oauth_signature_method {}", m)
+            }
+            VerifyError::UnknownConsumer => write!(f, "unknown consumer key"),
+            VerifyError::UnknownToken => write!(f, "unknown or revoked token"),
+            VerifyError::BadTimestamp => write!(f, "timestamp out of bounds"),
+            VerifyError::NonceReused => write!(f, "nonce has already been used"),
+            VerifyError::BadSignature => write!(f, "signature does not match"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<HeaderError> for VerifyError {
+    fn from(e: HeaderError) -> Self {
+        VerifyError::Header(e)
+    }
+}
+
+pub struct Verifier {
+    secrets: Box<dyn SecretStore>,
+    nonces: Box<dyn NonceStore>,
+    methods: Vec<Box<dyn SignatureVerifier>>,
+    window: u64,
+    clock: Box<dyn Clock>,
+}
+
+impl Verifier {
+    /// Accepts HMAC-SHA1 within five minutes of now, and keeps nonces in
+    /// memory.
+    pub fn new(secrets: impl SecretStore + 'static) -> Self {
+        Verifier {
+            secrets: Box::new(secrets),
+            nonces: Box::new(MemoryNonceStore::new()),
+            methods: vec![Box::new(HmacSha1)],
+            window: DEFAULT_WINDOW_SECS,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    pub fn with_nonce_store(mut self, store: impl NonceStore + 'static) -> Self {
+        self.nonces = Box::new(store);
+        self
+    }
+
+    /// Accepts another signature method on top of the ones already accepted.
+    pub fn with_signature_method(mut self, method: impl SignatureVerifier + 'static) -> Self {
+        self.methods.push(Box::new(method));
+        self
+    }
+
+    pub fn with_window(mut self, secs: u64) -> Self {
+        self.window = secs;
+        self
+    }
+
+    /// What the timestamp window is measured from.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn verify(&self, request: &SignedRequest) -> Result<Verified, VerifyError> {
+        let header: OAuthHeader = request.authorization.parse()?;
+
+        if let Some(version) = header.version.as_ref() {
+            if version != "1.0" {
+                return Err(VerifyError::UnsupportedVersion(version.clone()));
+            }
+        }
+
+        let method = self
+            .methods
+            .iter()
+            .find(|m| m.name() == header.signature_method)
+            .ok_or_else(|| {
+                VerifyError::UnsupportedSignatureMethod(header.signature_method.clone())
+            })?;
+
+        let consumer_secret = self
+            .secrets
+            .consumer_secret(&header.consumer_key)
+            .ok_or(VerifyError::UnknownConsumer)?;
+        let token_secret = match header.token.as_ref() {
+            Some(t) => self
+                .secrets
+                .token_secret(&header.consumer_key, t)
+                .ok_or(VerifyError::UnknownToken)?,
+            None => String::new(),
+        };
+
+        let now = self.clock.now();
+        if header.timestamp.saturating_add(self.window) < now
+            || header.timestamp > now.saturating_add(self.window)
+        {
+            return Err(VerifyError::BadTimestamp);
+        }
+
+        let mut params = header.params();
+        if Parameter::is_form(request.content_type) {
+            params.extend(Parameter::from_form(request.body));
+        }
+
+        let base_string = signature::base_string(request.method, request.url, params);
+        if !method.verify(
+            &base_string,
+            &consumer_secret,
+            &token_secret,
+            &header.signature,
+        ) {
+            return Err(VerifyError::BadSignature);
+        }
+
+        // only burn the nonce once we know the request is real, so nobody
+        // can use up someone else's nonces with garbage requests
+        let fresh = self.nonces.insert(
+            Nonce {
+                consumer_key: header.consumer_key.clone(),
+                token: header.token.clone(),
+                timestamp: header.timestamp,
+                value: header.nonce.clone(),
+            },
+            now.saturating_sub(self.window),
+        );
+        if !fresh {
+            return Err(VerifyError::NonceReused);
+        }
+
+        Ok(Verified {
+            consumer_key: header.consumer_key,
+            token: header.token,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::client::OAuthClient;
+    use super::super::sources::{FixedClock, FixedNonce};
+    use super::super::Authenticator;
+    use super::*;
+    use reqwest::blocking::{Client, Request};
+    use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+
+    fn verifier() -> Verifier {
+        Verifier::new(
+            StaticSecrets::new()
+                .with_consumer("app-key", "app-secret")
+                .with_token("app-key", "user-token", "user-secret"),
+        )
+    }
+
+    fn client() -> OAuthClient {
+        OAuthClient::new(
+            "app-key".to_string(),
+            "app-secret".to_string(),
+            "user-token".to_string(),
+            "user-secret".to_string(),
+        )
+    }
+
+    fn signed_post() -> Request {
+        let request = Client::new()
+            .post("https://api.twitter.com/1.1/statuses/update.json?include_entities=true")
+            .form(&[("status", "it's fox friday!"), ("media_ids", "1,2")])
+            .build()
+            .unwrap();
+
+        client().auth_request(request).unwrap()
+    }
+
+    fn verify_request(verifier: &Verifier, request: &Request) -> Result<Verified, VerifyError> {
+        verifier.verify(&SignedRequest {
+            method: request.method(),
+            url: request.url(),
+            authorization: request.headers()[AUTHORIZATION].to_str().unwrap(),
+            content_type: request
+                .headers()
+                .get(CONTENT_TYPE)
+                .map(|v| v.to_str().unwrap()),
+            body: request.body().and_then(|b| b.as_bytes()).unwrap_or(&[]),
+        })
+    }
+
+    fn header_at(timestamp: u64, nonce: &str) -> String {
+        let request = Client::new()
+            .get("https://api.twitter.com/1.1/account/verify_credentials.json")
+            .build()
+            .unwrap();
+        let request = client()
+            .with_clock(FixedClock(timestamp))
+            .with_nonce_source(FixedNonce(nonce.to_string()))
+            .auth_request(request)
+            .unwrap();
+
+        request.headers()[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn verify_header(verifier: &Verifier, header: &str) -> Result<Verified, VerifyError> {
+        let url =
+            Url::parse("https://api.twitter.com/1.1/account/verify_credentials.json").unwrap();
+
+        verifier.verify(&SignedRequest {
+            method: &Method::GET,
+            url: &url,
+            authorization: header,
+            content_type: None,
+            body: &[],
+        })
+    }
+
+    fn now() -> u64 {
+        SystemClock.now()
+    }
+
+    #[test]
+    fn test_verify_signed_request() {
+        let verified = verify_request(&verifier(), &signed_post()).unwrap();
+
+        assert_eq!(verified.consumer_key, "app-key");
+        assert_eq!(verified.token.as_deref(), Some("user-token"));
+    }
+
+    #[test]
+    fn test_reject_tampered_body() {
+        let mut request = signed_post();
+        *request.body_mut() = Some("status=it%27s+not+fox+friday&media_ids=1%2C2".into());
+
+        assert_eq!(
+            verify_request(&verifier(), &request),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_reject_wrong_secret() {
+        let verifier = Verifier::new(
+            StaticSecrets::new()
+                .with_consumer("app-key", "app-secret")
+                .with_token("app-key", "user-token", "some-other-secret"),
+        );
+
+        assert_eq!(
+            verify_request(&verifier, &signed_post()),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_reject_unknown_credentials() {
+        let verifier = Verifier::new(StaticSecrets::new().with_consumer("app-key", "app-secret"));
+        assert_eq!(
+            verify_request(&verifier, &signed_post()),
+            Err(VerifyError::UnknownToken)
+        );
+
+        let verifier = Verifier::new(StaticSecrets::new());
+        assert_eq!(
+            verify_request(&verifier, &signed_post()),
+            Err(VerifyError::UnknownConsumer)
+        );
+    }
+
+    #[test]
+    fn test_reject_replayed_nonce() {
+        let verifier = verifier();
+        let request = signed_post();
+
+        assert!(verify_request(&verifier, &request).is_ok());
+        assert_eq!(
+            verify_request(&verifier, &request),
+            Err(VerifyError::NonceReused)
+        );
+    }
+
+    #[test]
+    fn test_timestamp_window() {
+        let verifier = verifier();
+
+        assert!(verify_header(&verifier, &header_at(now() - 60, "a")).is_ok());
+        assert!(verify_header(&verifier, &header_at(now() + 60, "b")).is_ok());
+        assert_eq!(
+            verify_header(&verifier, &header_at(now() - 3600, "c")),
+            Err(VerifyError::BadTimestamp)
+        );
+        assert_eq!(
+            verify_header(&verifier, &header_at(now() + 3600, "d")),
+            Err(VerifyError::BadTimestamp)
+        );
+
+        let narrow = self::verifier().with_window(10);
+        assert_eq!(
+            verify_header(&narrow, &header_at(now() - 60, "e")),
+            Err(VerifyError::BadTimestamp)
+        );
+
+        // the timestamp's the client's to pick, so none of this can overflow
+        assert_eq!(
+            verify_header(&verifier, &header_at(u64::MAX, "f")),
+            Err(VerifyError::BadTimestamp)
+        );
+        let forever = self::verifier().with_window(u64::MAX);
+        assert!(verify_header(&forever, &header_at(u64::MAX, "g")).is_ok());
+    }
+
+    #[test]
+    fn test_verifier_clock() {
+        // an old request is fine if the verifier thinks it's still that time
+        let header = header_at(1318622958, "a");
+
+        assert_eq!(
+            verify_header(&verifier(), &header),
+            Err(VerifyError::BadTimestamp)
+        );
+        assert!(
+            verify_header(&verifier().with_clock(FixedClock(1318622958 + 60)), &header).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_same_nonce_different_timestamp() {
+        let verifier = verifier();
+        let now = now();
+
+        assert!(verify_header(&verifier, &header_at(now, "same")).is_ok());
+        assert!(verify_header(&verifier, &header_at(now - 1, "same")).is_ok());
+    }
+
+    #[test]
+    fn test_malformed_header() {
+        let verifier = verifier();
+
+        assert_eq!(
+            verify_header(&verifier, "Bearer abc"),
+            Err(VerifyError::Header(HeaderError::NotOAuth))
+        );
+        assert!(matches!(
+            verify_header(&verifier, "OAuth oauth_consumer_key=app-key"),
+            Err(VerifyError::Header(HeaderError::Malformed(_)))
+        ));
+        assert_eq!(
+            verify_header(&verifier, "OAuth oauth_consumer_key=\"app-key\""),
+            Err(VerifyError::Header(HeaderError::MissingField(
+                "oauth_signature_method"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_unsupported_method() {
+        let header = header_at(now(), "f").replace("HMAC-SHA1", "HMAC-SHA256");
+        assert_eq!(
+            verify_header(&verifier(), &header),
+            Err(VerifyError::UnsupportedSignatureMethod(
+                "HMAC-SHA256".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_custom_nonce_store() {
+        struct RejectEverything;
+
+        impl NonceStore for RejectEverything {
+            fn insert(&self, _: Nonce, _: u64) -> bool {
+                false
+            }
+        }
+
+        let verifier = verifier().with_nonce_store(RejectEverything);
+        assert_eq!(
+            verify_request(&verifier, &signed_post()),
+            Err(VerifyError::NonceReused)
+        );
+    }
+}
@@ -1,20 +1,69 @@
-use log::{debug, info};
-use reqwest::{
-    blocking::{multipart, Client},
-    Url,
-};
-use serde::Deserialize;
+use log::{debug, info, warn};
+use reqwest::{multipart, Client, Request, Response, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize};
 // use serde_json::from_str;
-use crate::oauth::{client, parameter::Parameter};
-use std::collections::HashMap;
-use std::env;
+use crate::credentials::{self, CredentialProvider, Keyring};
+use crate::oauth::sources::{Clock, SystemClock};
+use crate::oauth::{bearer, client, AsyncAuthenticator};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::thread::sleep;
 use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::sleep;
 
+pub mod blocking;
+mod error;
+mod rate_limit;
+mod resume;
+mod retry;
+
+pub use self::error::{ApiCode, BotError};
+pub use self::rate_limit::{RateLimit, RateLimits};
+pub use self::resume::{FileUploadStore, MemoryUploadStore, UploadState, UploadStore};
+pub use self::retry::RetryPolicy;
+
+use self::retry::Idempotency;
+
+/// Talks to the Twitter API. Everything's async; `blocking::Bot` wraps this
+/// for the CLI side of things.
 pub struct Bot {
-    authenticator: client::OAuthClient,
+    authenticator: Box<dyn AsyncAuthenticator>,
+    app_authenticator: bearer::BearerClient,
     client: Client,
+    api_url: String,
+    upload_url: String,
+    rate_limits: RateLimits,
+    /// How long we're willing to sleep for a rate limit to reset. `None`
+    /// means don't, just return the error.
+    rate_limit_wait: Option<Duration>,
+    retry: RetryPolicy,
+    upload_store: Option<Box<dyn UploadStore>>,
+    chunk_size: usize,
+    /// How many APPENDs can be going at once.
+    upload_parallelism: usize,
+    clock: Box<dyn Clock>,
+}
+
+pub const API_URL: &str = "https://api.twitter.com";
+pub const UPLOAD_URL: &str = "https://upload.twitter.com";
+
+pub const CHUNK_SIZE: usize = 1024 * 1000;
+/// Twitter won't take an APPEND bigger than this.
+pub const MAX_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+/// Or a segment_index past 999.
+pub const MAX_SEGMENTS: u16 = 1000;
+pub const UPLOAD_PARALLELISM: usize = 4;
+/// How long to wait between STATUS checks when twitter doesn't say.
+const CHECK_AFTER_SECS: u64 = 1;
+
+/// Which credentials an endpoint gets called with.
+#[derive(Clone, Copy)]
+enum Context {
+    /// Acting as the bot's account, for anything that posts.
+    User,
+    /// App-only, for read-only endpoints like search.
+    App,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,7 +73,8 @@ pub struct Errors {
 
 impl fmt::Display for Errors {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        let errors: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", errors.join("; "))
     }
 }
 
@@ -37,14 +87,35 @@ pub struct Error {
     message: String,
 }
 
+impl Error {
+    pub fn code(&self) -> ApiCode {
+        ApiCode::from_code(self.code)
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{} ({})", self.message, self.code)
     }
 }
 
 impl std::error::Error for Error {}
 
+#[derive(Debug, Deserialize)]
+pub struct Status {
+    pub id_str: String,
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResults {
+    statuses: Vec<Status>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Media {
     media_id: usize,
@@ -66,48 +137,256 @@ pub struct MediaStatus {
 type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
 
 impl Bot {
+    /// Credentials from the environment, plus the file, directory or secret
+    /// it points to (see `credentials::from_env`).
     pub fn new_from_env() -> Result<Self, SyncError> {
-        let app_key = match env::var("TWAPP_KEY") {
-            Ok(val) => val,
-            Err(e) => return Err(Box::new(e)),
-        };
-        let app_secret = match env::var("TWAPP_SECRET") {
-            Ok(val) => val,
-            Err(e) => return Err(Box::new(e)),
-        };
+        Self::new_from_provider(&credentials::from_env()?)
+    }
 
-        let user_token = match env::var("TWUSER_TOKEN") {
-            Ok(val) => val,
-            Err(e) => return Err(Box::new(e)),
-        };
-        let user_secret = match env::var("TWUSER_SECRET") {
-            Ok(val) => val,
-            Err(e) => return Err(Box::new(e)),
-        };
+    pub fn new_from_provider(credentials: &dyn CredentialProvider) -> Result<Self, SyncError> {
+        let app_key = credentials.require(credentials::APP_KEY)?;
+        let app_secret = credentials.require(credentials::APP_SECRET)?;
+        let user_token = credentials.require(credentials::USER_TOKEN)?;
+        let user_secret = credentials.require(credentials::USER_SECRET)?;
 
         Self::new(app_key, app_secret, user_token, user_secret)
     }
 
+    /// One of the credential sets in an encrypted keyring.
+    pub fn new_from_keyring(keyring: &Keyring, name: &str) -> Result<Self, SyncError> {
+        Self::new_from_provider(keyring.get(name)?)
+    }
+
     pub fn new(
         app_key: String,
         app_secret: String,
         user_token: String,
         user_secret: String,
+    ) -> Result<Self, SyncError> {
+        let user = client::OAuthClient::new(
+            app_key.clone(),
+            app_secret.clone(),
+            user_token,
+            user_secret,
+        );
+
+        Self::new_with_authenticator(app_key, app_secret, user)
+    }
+
+    /// Acts as the account with something other than an `OAuthClient`. The
+    /// endpoints the bot posts to are v1.1, which only take OAuth 1.0a user
+    /// context, so an OAuth 2.0 `pkce::PkceClient` won't get far here. The
+    /// app credentials are still used for app-only endpoints.
+    pub fn new_with_authenticator(
+        app_key: String,
+        app_secret: String,
+        user: impl AsyncAuthenticator + 'static,
     ) -> Result<Self, SyncError> {
         Ok(Bot {
-            authenticator: client::OAuthClient::new(app_key, app_secret, user_token, user_secret),
+            authenticator: Box::new(user),
+            app_authenticator: bearer::BearerClient::new(app_key, app_secret),
             client: Client::builder()
                 .user_agent("OAuth fox-friday-bot")
                 .build()?,
+            api_url: API_URL.to_string(),
+            upload_url: UPLOAD_URL.to_string(),
+            rate_limits: RateLimits::new(),
+            rate_limit_wait: None,
+            retry: RetryPolicy::default(),
+            upload_store: None,
+            chunk_size: CHUNK_SIZE,
+            upload_parallelism: UPLOAD_PARALLELISM,
+            clock: Box::new(SystemClock),
         })
     }
 
-    pub fn tweet_status_with_media(
+    /// Talks to somewhere other than api.twitter.com, e.g. a mock server.
+    /// The bearer token for app-only calls comes from there too.
+    pub fn with_api_url(mut self, url: &str) -> Self {
+        let url = url.trim_end_matches('/');
+        self.app_authenticator = self.app_authenticator.with_endpoints(
+            &format!("{}/oauth2/token", url),
+            &format!("{}/oauth2/invalidate_token", url),
+        );
+        self.api_url = url.to_string();
+        self
+    }
+
+    /// Uploads media somewhere other than upload.twitter.com.
+    pub fn with_upload_url(mut self, url: &str) -> Self {
+        self.upload_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sleeps until the window resets instead of failing when an endpoint's
+    /// out of requests, as long as that's no more than `max_wait` away.
+    pub fn with_rate_limit_wait(mut self, max_wait: Duration) -> Self {
+        self.rate_limit_wait = Some(max_wait);
+        self
+    }
+
+    /// How transient failures get retried. Calls that could do something
+    /// twice, like posting, are only retried when they can't have gone
+    /// through.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Saves upload progress here, so `upload_media` can resume.
+    pub fn with_upload_store(mut self, store: impl UploadStore + 'static) -> Self {
+        self.upload_store = Some(Box::new(store));
+        self
+    }
+
+    /// How much of the media goes in each APPEND, up to `MAX_CHUNK_SIZE`.
+    /// Media that'd take more than `MAX_SEGMENTS` chunks gets bigger ones.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.clamp(1, MAX_CHUNK_SIZE);
+        self
+    }
+
+    /// How many chunks get uploaded at once. Each one's held in memory
+    /// until it's gone through, so this times the chunk size is about how
+    /// much an upload needs.
+    pub fn with_upload_parallelism(mut self, parallelism: usize) -> Self {
+        self.upload_parallelism = parallelism.max(1);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// The latest `x-rate-limit-*` numbers for every endpoint called so far.
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    fn media_upload_url(&self) -> String {
+        format!("{}/1.1/media/upload.json", self.upload_url)
+    }
+
+    fn auth(&self, context: Context) -> &dyn AsyncAuthenticator {
+        match context {
+            Context::User => self.authenticator.as_ref(),
+            Context::App => &self.app_authenticator,
+        }
+    }
+
+    /// Sends the request `build` makes, retrying by the policy. It gets
+    /// built (and so signed, nonce and timestamp and all) fresh every try.
+    /// Anything but a success comes back as an error.
+    async fn execute<F>(
+        &self,
+        context: Context,
+        idempotency: Idempotency,
+        build: F,
+    ) -> Result<Response, BotError>
+    where
+        F: Fn() -> Result<Request, BotError>,
+    {
+        let mut attempt = 0;
+        let mut waited_for_reset = false;
+        loop {
+            let request = build()?;
+            let endpoint = request.url().path().to_string();
+
+            // known to be out already, no sense asking
+            if let Some(limit) = self.rate_limits.get(&endpoint) {
+                if limit.is_exhausted() {
+                    self.wait_for_reset(&endpoint, limit).await;
+                }
+            }
+
+            attempt += 1;
+            let err = match self.send(context, &endpoint, request).await {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+
+            // waiting out the window doesn't use up a try, the first time
+            let limit = self.rate_limits.get(&endpoint);
+            if let (BotError::Api { status, .. }, Some(limit)) = (&err, limit) {
+                if *status == StatusCode::TOO_MANY_REQUESTS
+                    && !waited_for_reset
+                    && self.wait_for_reset(&endpoint, limit).await
+                {
+                    waited_for_reset = true;
+                    attempt -= 1;
+                    continue;
+                }
+            }
+
+            if !self.retry.should_retry(&err, idempotency, attempt) {
+                return Err(err);
+            }
+            let delay = self.retry.delay(attempt - 1);
+            warn!("{} failed ({}), retrying in {:?}", endpoint, err, delay);
+            sleep(delay).await;
+        }
+    }
+
+    async fn send(
+        &self,
+        context: Context,
+        endpoint: &str,
+        request: Request,
+    ) -> Result<Response, BotError> {
+        let response = self
+            .auth(context)
+            .execute_async(&self.client, request)
+            .await
+            .map_err(BotError::auth)?;
+        self.rate_limits.update(endpoint, response.headers());
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(BotError::api(status, &response.text().await?));
+        }
+
+        Ok(response)
+    }
+
+    /// Sleeps until `limit` resets, if we're allowed to wait that long.
+    async fn wait_for_reset(&self, endpoint: &str, limit: RateLimit) -> bool {
+        let wait = limit.reset_in(self.clock.now());
+        match self.rate_limit_wait {
+            Some(max) if wait <= max => {
+                info!("Rate limited on {}, waiting {:?}", endpoint, wait);
+                sleep(wait).await;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn parse<T: DeserializeOwned>(response: Response) -> Result<T, BotError> {
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+
+    /// Recent tweets matching the query. Only needs the app credentials.
+    pub async fn search_statuses(&self, query: &str) -> Result<Vec<Status>, BotError> {
+        let url = Url::parse_with_params(
+            &format!("{}/1.1/search/tweets.json", self.api_url),
+            &[("q", query), ("result_type", "recent")],
+        )?;
+        let response = self
+            .execute(Context::App, Idempotency::Idempotent, || {
+                Ok(self.client.get(url.clone()).build()?)
+            })
+            .await?;
+
+        let results: SearchResults = Self::parse(response).await?;
+        Ok(results.statuses)
+    }
+
+    pub async fn tweet_status_with_media(
         &self,
         status: String,
         media: Vec<Media>,
-    ) -> Result<(), SyncError> {
-        let mut parameters = vec![Parameter::new("status", status.clone())];
+    ) -> Result<(), BotError> {
         let mut form = HashMap::new();
         form.insert("status", &status);
 
@@ -118,86 +397,228 @@ impl Bot {
                 .map(|m| m.media_id_string.clone())
                 .collect::<Vec<String>>()
                 .join(",");
-            parameters.push(Parameter::new("media_ids", media_ids.clone()));
             form.insert("media_ids", &media_ids);
         }
 
-        let mut request = self
-            .client
-            .post("https://api.twitter.com/1.1/statuses/update.json")
-            .form(&form)
-            .build()?;
-        request = self.authenticator.auth_request(request, parameters)?;
-        let response = self.client.execute(request)?;
-
-        if !response.status().is_success() {
-            let err: Errors = response.json()?;
-            return Err(Box::new(err));
-        }
+        let url = format!("{}/1.1/statuses/update.json", self.api_url);
+        self.execute(Context::User, Idempotency::NotIdempotent, || {
+            Ok(self.client.post(&url).form(&form).build()?)
+        })
+        .await?;
 
         Ok(())
     }
 
-    pub fn upload_media(
+    /// Uploads the video in chunks. With an upload store, progress is saved
+    /// after every chunk and an upload that didn't finish last time gets
+    /// picked up where it left off, as long as `file` is the same one again.
+    pub async fn upload_media(
         &self,
-        file: impl std::io::Read,
+        mut file: impl AsyncRead + Unpin,
         file_len: usize,
-    ) -> Result<Media, SyncError> {
-        info!("Initializing media upload now.");
-        let mut media = self.init_media_upload(file_len)?;
-        debug!(
-            "Media expiration: {} minutes",
-            media.expires_after_secs.unwrap() / 60
-        );
-        info!("Got media ID: {}", media.media_id_string);
-        sleep(Duration::from_secs(1));
-        self.chunked_file_upload(file, file_len, media.media_id_string.clone())?;
-        info!("Successfully uploaded media to endpoint.");
-        media = self.finalize_media_upload(media.media_id_string.clone())?;
+    ) -> Result<Media, BotError> {
+        let chunk_size = self.chunk_size_for(file_len)?;
+        let mut state = match self.resumable_upload(file_len).await? {
+            Some(state) => {
+                info!(
+                    "Resuming upload of media ID {} at chunk {}.",
+                    state.media_id, state.next_segment
+                );
+                // can't seek, so read past what's already been sent
+                let sent = state.byte_offset as u64;
+                if tokio::io::copy(&mut (&mut file).take(sent), &mut tokio::io::sink()).await?
+                    != sent
+                {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                }
+                state
+            }
+            None => {
+                info!("Initializing media upload now.");
+                let media = self.init_media_upload(file_len).await?;
+                info!("Got media ID: {}", media.media_id_string);
+                let expires_after_secs = match media.expires_after_secs {
+                    Some(secs) => {
+                        debug!("Media expiration: {} minutes", secs / 60);
+                        secs as u64
+                    }
+                    None => {
+                        // as good as expired, for resuming
+                        warn!("No expiration given for the media, the upload can't be resumed.");
+                        0
+                    }
+                };
+
+                let state = UploadState {
+                    media_id: media.media_id_string,
+                    total_bytes: file_len,
+                    next_segment: 0,
+                    byte_offset: 0,
+                    expires_at: self.clock.now() + expires_after_secs,
+                };
+                self.save_upload(&state).await?;
+                sleep(Duration::from_secs(1)).await;
+                state
+            }
+        };
+
+        let finalized = match self.chunked_file_upload(file, chunk_size, &mut state).await {
+            Ok(()) => self.finalize_media_upload(state.media_id.clone()).await,
+            Err(err) => Err(err),
+        };
+        let mut media = match finalized {
+            Ok(media) => media,
+            Err(err) => {
+                // twitter's forgotten about it, so don't try it again
+                if err.api_code() == Some(ApiCode::InvalidMedia) {
+                    self.clear_upload().await?;
+                }
+                return Err(err);
+            }
+        };
+        self.clear_upload().await?;
         info!("Successfully finalized media upload.");
 
         if media.processing_info.is_some() {
             info!("Media processing detected, waiting until finished.");
-            let mut media_processing_info = media.processing_info.as_ref().unwrap().clone();
-            while media_processing_info.state != "succeeded" {
-                if media_processing_info.state == "failed" {
-                    return Err(Box::new(media_processing_info.error.unwrap()));
-                }
-
-                sleep(Duration::from_secs(
-                    media_processing_info.check_after_secs.unwrap() as u64,
-                ));
-                media = self.get_media_status(media.media_id_string.clone())?;
-                media_processing_info = media.processing_info.as_ref().unwrap().clone();
+        }
+        // STATUS drops processing_info once there's nothing left to wait for
+        while let Some(processing_info) = media.processing_info.clone() {
+            match processing_info.state.as_str() {
+                "succeeded" => break,
+                "failed" => return Err(BotError::MediaProcessing(processing_info.error)),
+                _ => {}
             }
+
+            let check_after_secs = processing_info
+                .check_after_secs
+                .map_or(CHECK_AFTER_SECS, |secs| secs as u64);
+            sleep(Duration::from_secs(check_after_secs)).await;
+            media = self.get_media_status(media.media_id_string.clone()).await?;
         }
 
         info!("Media successfully uploaded.");
         Ok(media)
     }
 
-    fn chunked_file_upload(
+    /// The stored upload, if there is one and it's still good for this file.
+    async fn resumable_upload(&self, file_len: usize) -> Result<Option<UploadState>, BotError> {
+        let store = match &self.upload_store {
+            Some(store) => store,
+            None => return Ok(None),
+        };
+
+        match store.load().await.map_err(BotError::UploadState)? {
+            Some(state) if state.resumes(file_len, self.clock.now()) => Ok(Some(state)),
+            Some(state) => {
+                info!(
+                    "Not resuming upload of media ID {}, starting over.",
+                    state.media_id
+                );
+                self.clear_upload().await?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save_upload(&self, state: &UploadState) -> Result<(), BotError> {
+        match &self.upload_store {
+            // nothing could resume it anyway
+            Some(_) if !state.resumes(state.total_bytes, self.clock.now()) => Ok(()),
+            Some(store) => store.save(state).await.map_err(BotError::UploadState),
+            None => Ok(()),
+        }
+    }
+
+    async fn clear_upload(&self) -> Result<(), BotError> {
+        match &self.upload_store {
+            Some(store) => store.clear().await.map_err(BotError::UploadState),
+            None => Ok(()),
+        }
+    }
+
+    // twitter only takes MAX_SEGMENTS chunks, so the chunk size has to be at
+    // least this big
+    fn chunk_size_for(&self, file_len: usize) -> Result<usize, BotError> {
+        let chunk_size = self
+            .chunk_size
+            .max(file_len.div_ceil(MAX_SEGMENTS as usize));
+        if chunk_size > MAX_CHUNK_SIZE {
+            return Err(BotError::MediaTooLarge(file_len));
+        }
+
+        Ok(chunk_size)
+    }
+
+    async fn chunked_file_upload(
         &self,
-        mut file: impl std::io::Read,
-        file_len: usize,
-        id: String,
-    ) -> Result<(), SyncError> {
-        // let mut chunk_amount = (len as f64 / (1024.0 * 1000.0)).ceil();
-        let mut chunk_index = 0;
-        let mut buf: [u8; 1024 * 1000] = [0; 1024 * 1000];
-        let mut total_bytes_read = 0; while total_bytes_read != file_len {
-            let bytes_read = file.read(&mut buf[..])?;
-            self.upload_media_chunk(id.clone(), chunk_index, buf[..bytes_read].to_vec())?;
-            info!("Uploaded chunk {}", chunk_index);
-
-            chunk_index += 1;
-            total_bytes_read += bytes_read;
+        mut file: impl AsyncRead + Unpin,
+        chunk_size: usize,
+        state: &mut UploadState,
+    ) -> Result<(), BotError> {
+        // twitter puts the segments back in order itself, so they can go up
+        // in any order. the state only moves past a chunk once everything
+        // before it's done too, so a resume might send a few again, which
+        // is fine, the same segment_index just gets replaced
+        let mut uploading = FuturesUnordered::new();
+        let mut uploaded = BTreeMap::new();
+        let (mut segment, mut offset) = (state.next_segment, state.byte_offset);
+        let mut read_err = None;
+
+        loop {
+            while read_err.is_none()
+                && uploading.len() < self.upload_parallelism
+                && offset < state.total_bytes
+            {
+                // only a resumed state that's been messed with gets here
+                if segment >= MAX_SEGMENTS {
+                    return Err(BotError::MediaTooLarge(state.total_bytes));
+                }
+
+                let len = chunk_size.min(state.total_bytes - offset);
+                let mut buf = vec![0u8; len];
+                if let Err(err) = file.read_exact(&mut buf).await {
+                    // let the ones that are going finish first, so they're
+                    // saved for next time
+                    read_err = Some(err);
+                    break;
+                }
+
+                let id = state.media_id.clone();
+                uploading.push(async move {
+                    self.upload_media_chunk(id, segment, buf)
+                        .await
+                        .map(|_| (segment, len))
+                });
+                segment += 1;
+                offset += len;
+            }
+
+            let (done, len) = match uploading.next().await {
+                Some(result) => result?,
+                None => break,
+            };
+            info!("Uploaded chunk {}", done);
+
+            uploaded.insert(done, len);
+            let next_segment = state.next_segment;
+            while let Some(len) = uploaded.remove(&state.next_segment) {
+                state.next_segment += 1;
+                state.byte_offset += len;
+            }
+            if state.next_segment != next_segment {
+                self.save_upload(state).await?;
+            }
         }
 
-        Ok(())
+        match read_err {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
     }
 
-    fn init_media_upload(&self, length: usize) -> Result<Media, SyncError> {
+    async fn init_media_upload(&self, length: usize) -> Result<Media, BotError> {
         let length_string = length.to_string();
         /*
         let mut form = HashMap::new();
@@ -214,32 +635,22 @@ impl Bot {
             ("media_type", "video/mp4")
         ];
 
-        let parameters = vec![
-            Parameter::new("command", "INIT"),
-            Parameter::new("total_bytes", &length.to_string()),
-            Parameter::new("media_category", "tweet_video"),
-            Parameter::new("media_type", "video/mp4"),
-        ];
-        let mut request = self
-            .client
-            .post("https://upload.twitter.com/1.1/media/upload.json")
-            .form(&form)
-            .build()?;
-        request = self.authenticator.auth_request(request, parameters)?;
-        let response = self.client.execute(request)?.text()?;
-
-        let media = serde_json::from_str(&response);
-
-        match media {
-            Ok(v) => Ok(v),
-            Err(_) => {
-                let err: Errors = serde_json::from_str(&response)?;
-                Err(Box::new(err))
-            }
-        }
+        // not strictly, a retried INIT can leave a media ID behind. nothing
+        // uses it and it expires, so that's fine
+        let response = self
+            .execute(Context::User, Idempotency::Idempotent, || {
+                Ok(self
+                    .client
+                    .post(self.media_upload_url())
+                    .form(&form)
+                    .build()?)
+            })
+            .await?;
+
+        Self::parse(response).await
     }
 
-    fn finalize_media_upload(&self, id: String) -> Result<Media, SyncError> {
+    async fn finalize_media_upload(&self, id: String) -> Result<Media, BotError> {
         /*
         let mut form = HashMap::new();
         form.insert("command", "FINALIZE");
@@ -251,81 +662,388 @@ impl Bot {
             ("media_id", &id)
         ];
 
-        let parameters = vec![
-            Parameter::new("command", "FINALIZE"),
-            Parameter::new("media_id", id.clone()),
-        ];
+        let response = self
+            .execute(Context::User, Idempotency::Idempotent, || {
+                Ok(self
+                    .client
+                    .post(self.media_upload_url())
+                    .form(&form)
+                    .build()?)
+            })
+            .await?;
 
-        let mut request = self
-            .client
-            .post("https://upload.twitter.com/1.1/media/upload.json")
-            .form(&form)
-            .build()?;
-        request = self.authenticator.auth_request(request, parameters)?;
-        let response = self.client.execute(request)?.text()?;
-
-        let media = serde_json::from_str(&response);
-
-        match media {
-            Ok(v) => Ok(v),
-            Err(_) => {
-                let err: Errors = serde_json::from_str(&response)?;
-                Err(Box::new(err))
-            }
-        }
+        Self::parse(response).await
     }
 
-    fn get_media_status(&self, id: String) -> Result<Media, SyncError> {
-        let parameters = vec![
-            Parameter::new("command", "STATUS"),
-            Parameter::new("media_id", id.clone()),
-        ];
-        let mut request = self
-            .client
-            .request(
-                reqwest::Method::GET,
-                Url::parse_with_params(
-                    "https://upload.twitter.com/1.1/media/upload.json",
-                    &[("command", "STATUS"), ("media_id", id.clone().as_str())],
-                )?,
-            )
-            .build()?;
-        request = self.authenticator.auth_request(request, parameters)?;
-        let response = self.client.execute(request)?.text()?;
-
-        let media = serde_json::from_str(&response);
-
-        match media {
-            Ok(v) => Ok(v),
-            Err(_) => {
-                let err: Errors = serde_json::from_str(&response)?;
-                Err(Box::new(err))
-            }
-        }
+    async fn get_media_status(&self, id: String) -> Result<Media, BotError> {
+        let url = Url::parse_with_params(
+            &self.media_upload_url(),
+            &[("command", "STATUS"), ("media_id", id.as_str())],
+        )?;
+        let response = self
+            .execute(Context::User, Idempotency::Idempotent, || {
+                Ok(self
+                    .client
+                    .request(reqwest::Method::GET, url.clone())
+                    .build()?)
+            })
+            .await?;
+
+        Self::parse(response).await
     }
 
     // big thanks to Keea (@keeakita) for telling me how a Cow works
     // to my head ass, saving me from an unsafe block :fox: :eye:
-    fn upload_media_chunk(&self, id: String, segment: u16, buf: Vec<u8>) -> Result<(), SyncError> {
-        let form = multipart::Form::new()
-            .text("command", "APPEND")
-            .text("media_id", id)
-            .text("segment_index", segment.to_string())
-            .part("media", multipart::Part::bytes(buf));
-
-        let mut request = self
-            .client
-            .post("https://upload.twitter.com/1.1/media/upload.json")
-            .multipart(form)
-            .build()?;
-        request = self.authenticator.auth_request(request, vec![])?;
-        let response = self.client.execute(request)?.text()?;
-
-        if !response.is_empty() {
-            let err: Errors = serde_json::from_str(&response)?;
-            return Err(Box::new(err));
-        }
+    async fn upload_media_chunk(
+        &self,
+        id: String,
+        segment: u16,
+        buf: Vec<u8>,
+    ) -> Result<(), BotError> {
+        // a multipart body can only be sent once, so it's made over for
+        // every try
+        self.execute(Context::User, Idempotency::Idempotent, || {
+            let form = multipart::Form::new()
+                .text("command", "APPEND")
+                .text("media_id", id.clone())
+                .text("segment_index", segment.to_string())
+                .part("media", multipart::Part::bytes(buf.clone()));
+
+            Ok(self
+                .client
+                .post(self.media_upload_url())
+                .multipart(form)
+                .build()?)
+        })
+        .await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::twitter::MockTwitter;
+    use crate::oauth::header::OAuthHeader;
+
+    fn bot(twitter: &MockTwitter) -> Bot {
+        Bot::new(
+            "app-key".to_string(),
+            "app-secret".to_string(),
+            "user-token".to_string(),
+            "user-secret".to_string(),
+        )
+        .unwrap()
+        .with_api_url(&twitter.url())
+        .with_upload_url(&twitter.url())
+        .with_retry_policy(RetryPolicy::new().with_base_delay(Duration::from_millis(10)))
+    }
+
+    // a couple of chunks and a bit
+    fn video() -> Vec<u8> {
+        (0..2_500_000).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn test_upload_and_post() {
+        let twitter = MockTwitter::start().with_processing(1);
+        let bot = bot(&twitter);
+        let video = video();
+
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        let id = media.media_id_string.clone();
+        assert_eq!(twitter.media(&id), Some(video));
+
+        bot.tweet_status_with_media("it's fox friday!".to_string(), vec![media])
+            .await
+            .unwrap();
+        let statuses = twitter.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].text, "it's fox friday!");
+        assert_eq!(statuses[0].media_ids, vec![id]);
+
+        // every call went out signed
+        assert!(twitter
+            .requests()
+            .iter()
+            .all(|r| r.header("authorization").unwrap().starts_with("OAuth ")));
+
+        let found = bot.search_statuses("fox").await.unwrap();
+        assert_eq!(found[0].text, "it's fox friday!");
+    }
+
+    #[tokio::test]
+    async fn test_parallel_upload() {
+        let twitter = MockTwitter::start().with_latency(Duration::from_millis(50));
+        let store = MemoryUploadStore::new();
+        let bot = bot(&twitter)
+            .with_chunk_size(100_000)
+            .with_upload_parallelism(4)
+            .with_upload_store(store);
+        let video = video();
+
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        assert_eq!(twitter.media(&media.media_id_string), Some(video));
+
+        let appends = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.body_text().contains("APPEND"))
+            .count();
+        assert_eq!(appends, 25);
+        assert!(twitter.max_concurrency() > 1);
+        assert!(twitter.max_concurrency() <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_small_chunks_are_raised() {
+        let twitter = MockTwitter::start();
+        let bot = bot(&twitter).with_chunk_size(1).with_upload_parallelism(8);
+        let video: Vec<u8> = video().into_iter().take(2_500).collect();
+
+        // 2,500 one byte chunks would be too many, so they're three bytes,
+        // the smallest that fits in 1,000
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        assert_eq!(twitter.media(&media.media_id_string), Some(video));
+        let appends = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.body_text().contains("APPEND"))
+            .count();
+        assert_eq!(appends, 834);
+
+        let err = bot
+            .upload_media(&b""[..], MAX_CHUNK_SIZE * MAX_SEGMENTS as usize + 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BotError::MediaTooLarge(_)));
+        let inits = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.body_text().contains("command=INIT"))
+            .count();
+        assert_eq!(inits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_api_errors() {
+        let twitter = MockTwitter::start();
+        let bot = bot(&twitter);
+
+        bot.tweet_status_with_media("hi".to_string(), vec![])
+            .await
+            .unwrap();
+        let err = bot
+            .tweet_status_with_media("hi".to_string(), vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(ApiCode::DuplicateStatus));
+
+        let err = bot
+            .finalize_media_upload("12345".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(ApiCode::InvalidMedia));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits() {
+        let endpoint = "/1.1/statuses/update.json";
+        let twitter = MockTwitter::start().with_rate_limit(endpoint, 1, 2);
+        let bot = bot(&twitter);
+
+        bot.tweet_status_with_media("one".to_string(), vec![])
+            .await
+            .unwrap();
+        let limit = bot.rate_limits().get(endpoint).unwrap();
+        assert_eq!((limit.limit, limit.remaining), (1, 0));
+
+        let err = bot
+            .tweet_status_with_media("two".to_string(), vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(err.api_code(), Some(ApiCode::RateLimitExceeded));
+        assert!(err.is_retryable());
+
+        // waits out the window it knows is used up
+        let bot = bot.with_rate_limit_wait(Duration::from_secs(5));
+        bot.tweet_status_with_media("two".to_string(), vec![])
+            .await
+            .unwrap();
+
+        // and one that doesn't know yet waits after the 429
+        let other = self::bot(&twitter).with_rate_limit_wait(Duration::from_secs(5));
+        other
+            .tweet_status_with_media("three".to_string(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(twitter.statuses().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let upload = "/1.1/media/upload.json";
+        let twitter = MockTwitter::start().with_failures(upload, Some("APPEND"), 2, 503);
+        let bot = bot(&twitter);
+
+        bot.upload_media(&b"fox"[..], 3).await.unwrap();
+
+        // signed over again every time
+        let appends: Vec<String> = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.path == upload && r.method == "POST")
+            .filter(|r| r.header("content-type").unwrap().starts_with("multipart"))
+            .map(|r| {
+                let header = r.header("authorization").unwrap();
+                header.parse::<OAuthHeader>().unwrap().nonce
+            })
+            .collect();
+        assert_eq!(appends.len(), 3);
+        assert_ne!(appends[0], appends[1]);
+        assert_ne!(appends[1], appends[2]);
+
+        // out of tries
+        let twitter = MockTwitter::start().with_failures(upload, Some("APPEND"), 4, 503);
+        let err = self::bot(&twitter)
+            .upload_media(&b"fox"[..], 3)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "twitter returned 503 Service Unavailable: Internal error (131)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_posting_is_not_retried() {
+        let update = "/1.1/statuses/update.json";
+        let twitter = MockTwitter::start().with_failures(update, None, 1, 503);
+        let bot = bot(&twitter);
+
+        // it might've gone through, so it's up to the caller
+        let err = bot
+            .tweet_status_with_media("fox".to_string(), vec![])
+            .await
+            .unwrap_err();
+        assert!(err.is_retryable());
+        let posts = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.path == update)
+            .count();
+        assert_eq!(posts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_resumed_upload() {
+        let twitter = MockTwitter::start();
+        let bot = bot(&twitter).with_upload_store(MemoryUploadStore::new());
+        let video = video();
+
+        // falls over partway through the second chunk
+        let err = bot
+            .upload_media(&video[..1_500_000], video.len())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BotError::Io(_)));
+
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        assert_eq!(twitter.media(&media.media_id_string), Some(video.clone()));
+        let inits = twitter
+            .requests()
+            .iter()
+            .filter(|r| r.body_text().contains("command=INIT"))
+            .count();
+        assert_eq!(inits, 1);
+
+        // done with, so the next one starts from scratch
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        assert_eq!(twitter.media(&media.media_id_string), Some(video));
+    }
+
+    #[tokio::test]
+    async fn test_upload_without_expiry() {
+        let twitter = MockTwitter::start().with_media_expiry(None);
+        let path = std::env::temp_dir().join(format!(
+            "fox-friday-upload-no-expiry-{}.json",
+            std::process::id()
+        ));
+        let bot = bot(&twitter).with_upload_store(FileUploadStore::new(&path));
+        let video = video();
+
+        let err = bot
+            .upload_media(&video[..1_500_000], video.len())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BotError::Io(_)));
+        // no state that's expired before it's even saved
+        assert!(!path.exists());
+
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        assert_eq!(twitter.media(&media.media_id_string), Some(video));
+    }
+
+    #[tokio::test]
+    async fn test_stale_upload_is_not_resumed() {
+        let twitter = MockTwitter::start();
+        let store = MemoryUploadStore::new();
+        store
+            .save(&UploadState {
+                media_id: "42".to_string(),
+                total_bytes: 3,
+                next_segment: 1,
+                byte_offset: 1,
+                expires_at: 0,
+            })
+            .await
+            .unwrap();
+        let bot = bot(&twitter).with_upload_store(store);
+
+        let media = bot.upload_media(&b"fox"[..], 3).await.unwrap();
+        assert_ne!(media.media_id_string, "42");
+        assert_eq!(twitter.media(&media.media_id_string).unwrap(), b"fox");
+    }
+
+    #[tokio::test]
+    async fn test_processing_without_check_after() {
+        let twitter = MockTwitter::start().with_processing(2).with_bare_status();
+        let bot = bot(&twitter);
+        let video = video();
+
+        let media = bot
+            .upload_media(video.as_slice(), video.len())
+            .await
+            .unwrap();
+        assert!(media.processing_info.is_none());
+        assert_eq!(twitter.media(&media.media_id_string), Some(video));
+    }
+
+    #[tokio::test]
+    async fn test_failed_processing() {
+        let twitter = MockTwitter::start().with_failed_processing();
+        let bot = bot(&twitter);
+
+        let err = bot.upload_media(&b"not a video"[..], 11).await.unwrap_err();
+        assert!(matches!(err, BotError::MediaProcessing(Some(_))));
+    }
+}
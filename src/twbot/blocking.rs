@@ -0,0 +1,171 @@
+// the async Bot, for things that don't have a runtime of their own (the CLI
+// bins, tests). each call just runs the async one to completion on a small
+// runtime this owns, so don't use it from inside another runtime.
+
+use super::{BotError, Media, RateLimits, RetryPolicy, Status, SyncError, UploadStore};
+use crate::credentials::{CredentialProvider, Keyring};
+use crate::oauth::AsyncAuthenticator;
+use std::io::Read;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::runtime::{Builder, Runtime};
+
+pub struct Bot {
+    inner: super::Bot,
+    runtime: Runtime,
+}
+
+impl Bot {
+    pub fn new_from_env() -> Result<Self, SyncError> {
+        Self::wrap(super::Bot::new_from_env()?)
+    }
+
+    pub fn new_from_provider(credentials: &dyn CredentialProvider) -> Result<Self, SyncError> {
+        Self::wrap(super::Bot::new_from_provider(credentials)?)
+    }
+
+    pub fn new_from_keyring(keyring: &Keyring, name: &str) -> Result<Self, SyncError> {
+        Self::wrap(super::Bot::new_from_keyring(keyring, name)?)
+    }
+
+    pub fn new(
+        app_key: String,
+        app_secret: String,
+        user_token: String,
+        user_secret: String,
+    ) -> Result<Self, SyncError> {
+        Self::wrap(super::Bot::new(
+            app_key,
+            app_secret,
+            user_token,
+            user_secret,
+        )?)
+    }
+
+    pub fn new_with_authenticator(
+        app_key: String,
+        app_secret: String,
+        user: impl AsyncAuthenticator + 'static,
+    ) -> Result<Self, SyncError> {
+        Self::wrap(super::Bot::new_with_authenticator(
+            app_key, app_secret, user,
+        )?)
+    }
+
+    pub fn with_api_url(mut self, url: &str) -> Self {
+        self.inner = self.inner.with_api_url(url);
+        self
+    }
+
+    pub fn with_upload_url(mut self, url: &str) -> Self {
+        self.inner = self.inner.with_upload_url(url);
+        self
+    }
+
+    pub fn with_rate_limit_wait(mut self, max_wait: Duration) -> Self {
+        self.inner = self.inner.with_rate_limit_wait(max_wait);
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.inner = self.inner.with_retry_policy(retry);
+        self
+    }
+
+    pub fn with_upload_store(mut self, store: impl UploadStore + 'static) -> Self {
+        self.inner = self.inner.with_upload_store(store);
+        self
+    }
+
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.inner = self.inner.with_chunk_size(size);
+        self
+    }
+
+    pub fn with_upload_parallelism(mut self, parallelism: usize) -> Self {
+        self.inner = self.inner.with_upload_parallelism(parallelism);
+        self
+    }
+
+    pub fn rate_limits(&self) -> &RateLimits {
+        self.inner.rate_limits()
+    }
+
+    fn wrap(inner: super::Bot) -> Result<Self, SyncError> {
+        Ok(Bot {
+            inner,
+            runtime: Builder::new_current_thread().enable_all().build()?,
+        })
+    }
+
+    /// The async bot underneath, for anything that isn't wrapped here.
+    pub fn get_ref(&self) -> &super::Bot {
+        &self.inner
+    }
+
+    pub fn search_statuses(&self, query: &str) -> Result<Vec<Status>, BotError> {
+        self.runtime.block_on(self.inner.search_statuses(query))
+    }
+
+    pub fn tweet_status_with_media(
+        &self,
+        status: String,
+        media: Vec<Media>,
+    ) -> Result<(), BotError> {
+        self.runtime
+            .block_on(self.inner.tweet_status_with_media(status, media))
+    }
+
+    pub fn upload_media(
+        &self,
+        file: impl Read + Unpin,
+        file_len: usize,
+    ) -> Result<Media, BotError> {
+        self.runtime
+            .block_on(self.inner.upload_media(BlockingReader(file), file_len))
+    }
+}
+
+// reads on whatever thread polls it. fine here, the runtime's ours and has
+// nothing else to do while it waits
+struct BlockingReader<R>(R);
+
+impl<R: Read + Unpin> AsyncRead for BlockingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let read = self.get_mut().0.read(buf.initialize_unfilled())?;
+        buf.advance(read);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::twitter::MockTwitter;
+
+    #[test]
+    fn test_blocking_upload() {
+        let twitter = MockTwitter::start();
+        let bot = Bot::new(
+            "app-key".to_string(),
+            "app-secret".to_string(),
+            "user-token".to_string(),
+            "user-secret".to_string(),
+        )
+        .unwrap()
+        .with_api_url(&twitter.url())
+        .with_upload_url(&twitter.url());
+
+        let media = bot.upload_media(&b"fox"[..], 3).unwrap();
+        bot.tweet_status_with_media("fox".to_string(), vec![media])
+            .unwrap();
+        assert_eq!(twitter.statuses().len(), 1);
+    }
+}
@@ -0,0 +1,272 @@
+// everything a Bot call can fail with, sorted enough that callers can decide
+// what to do about it: wait and try again, give up, or go fix the tokens.
+
+use super::{Error, Errors};
+use reqwest::StatusCode;
+use std::fmt;
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+/// Twitter's error codes, the ones worth telling apart anyway. See
+/// https://developer.twitter.com/en/support/twitter-api/error-troubleshooting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApiCode {
+    /// 32
+    CouldNotAuthenticate,
+    /// 34, the page (or user, or status) doesn't exist.
+    NotFound,
+    /// 88
+    RateLimitExceeded,
+    /// 89, the token's expired or been revoked.
+    InvalidToken,
+    /// 130
+    OverCapacity,
+    /// 131
+    InternalError,
+    /// 135, our clock is too far off theirs.
+    TimestampOutOfBounds,
+    /// 185, the account's posted its daily limit.
+    StatusLimit,
+    /// 186
+    StatusTooLong,
+    /// 187, the same status was just posted.
+    DuplicateStatus,
+    /// 215
+    BadAuthentication,
+    /// 324, the media ID's wrong, expired, or the media didn't process.
+    InvalidMedia,
+    /// 326
+    AccountLocked,
+    Other(usize),
+}
+
+impl ApiCode {
+    pub fn from_code(code: usize) -> Self {
+        match code {
+            32 => ApiCode::CouldNotAuthenticate,
+            34 => ApiCode::NotFound,
+            88 => ApiCode::RateLimitExceeded,
+            89 => ApiCode::InvalidToken,
+            130 => ApiCode::OverCapacity,
+            131 => ApiCode::InternalError,
+            135 => ApiCode::TimestampOutOfBounds,
+            185 => ApiCode::StatusLimit,
+            186 => ApiCode::StatusTooLong,
+            187 => ApiCode::DuplicateStatus,
+            215 => ApiCode::BadAuthentication,
+            324 => ApiCode::InvalidMedia,
+            326 => ApiCode::AccountLocked,
+            other => ApiCode::Other(other),
+        }
+    }
+
+    /// Whether the same request could work if it's sent again later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiCode::RateLimitExceeded | ApiCode::OverCapacity | ApiCode::InternalError
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum BotError {
+    /// Couldn't get a response at all, or couldn't read it.
+    Http(reqwest::Error),
+    /// Something went wrong with the credentials before the request went
+    /// out (signing, refreshing a token, ...).
+    Auth(SyncError),
+    /// Twitter answered, but not with what we asked for. `errors` is empty
+    /// if the body didn't say why.
+    Api {
+        status: StatusCode,
+        errors: Vec<Error>,
+    },
+    /// The response wasn't the JSON we expected.
+    Json(serde_json::Error),
+    Url(url::ParseError),
+    Io(std::io::Error),
+    /// Fetching the media out of S3 failed.
+    S3(SyncError),
+    /// The upload store couldn't load or save the upload's progress.
+    UploadState(SyncError),
+    /// The upload went through, but Twitter couldn't process it.
+    MediaProcessing(Option<Error>),
+    /// Too big to fit in `MAX_SEGMENTS` chunks of `MAX_CHUNK_SIZE`. Holds
+    /// the size.
+    MediaTooLarge(usize),
+}
+
+impl BotError {
+    /// Makes an `Api` error out of a response body, whether or not it's the
+    /// usual `{"errors": [...]}`.
+    pub fn api(status: StatusCode, body: &str) -> Self {
+        let errors = serde_json::from_str::<Errors>(body)
+            .map(|e| e.errors)
+            .unwrap_or_default();
+        BotError::Api { status, errors }
+    }
+
+    /// Anything an authenticator returned; transport errors keep their type.
+    pub fn auth(err: SyncError) -> Self {
+        match err.downcast::<reqwest::Error>() {
+            Ok(err) => BotError::Http(*err),
+            Err(err) => BotError::Auth(err),
+        }
+    }
+
+    /// The first of Twitter's error codes, if it sent any.
+    pub fn api_code(&self) -> Option<ApiCode> {
+        match self {
+            BotError::Api { errors, .. } => errors.first().map(Error::code),
+            BotError::MediaProcessing(Some(error)) => Some(error.code()),
+            _ => None,
+        }
+    }
+
+    /// Whether the same call could work if it's made again later. Anything
+    /// that isn't known to be transient counts as not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BotError::Http(err) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err.status().filter(|s| s.is_server_error()).is_some()
+            }
+            BotError::Api { status, errors } => {
+                *status == StatusCode::TOO_MANY_REQUESTS
+                    || status.is_server_error()
+                    || errors.iter().any(|e| e.code().is_retryable())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotError::Http(err) => write!(f, "couldn't reach twitter: {}", err),
+            BotError::Auth(err) => write!(f, "couldn't authenticate the request: {}", err),
+            BotError::Api { status, errors } => {
+                write!(f, "twitter returned {}", status)?;
+                for (i, err) in errors.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, err)?;
+                }
+                Ok(())
+            }
+            BotError::Json(err) => write!(f, "unexpected response from twitter: {}", err),
+            BotError::Url(err) => write!(f, "bad url: {}", err),
+            BotError::Io(err) => write!(f, "couldn't read the media: {}", err),
+            BotError::S3(err) => write!(f, "couldn't get the media from s3: {}", err),
+            BotError::UploadState(err) => write!(f, "couldn't keep the upload state: {}", err),
+            BotError::MediaProcessing(Some(err)) => write!(f, "media processing failed: {}", err),
+            BotError::MediaProcessing(None) => write!(f, "media processing failed"),
+            BotError::MediaTooLarge(len) => write!(f, "media too large to upload: {} bytes", len),
+        }
+    }
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BotError::Http(err) => Some(err),
+            BotError::Auth(err) | BotError::S3(err) | BotError::UploadState(err) => {
+                Some(err.as_ref())
+            }
+            BotError::Json(err) => Some(err),
+            BotError::Url(err) => Some(err),
+            BotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BotError {
+    fn from(err: reqwest::Error) -> Self {
+        BotError::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for BotError {
+    fn from(err: serde_json::Error) -> Self {
+        BotError::Json(err)
+    }
+}
+
+impl From<url::ParseError> for BotError {
+    fn from(err: url::ParseError) -> Self {
+        BotError::Url(err)
+    }
+}
+
+impl From<std::io::Error> for BotError {
+    fn from(err: std::io::Error) -> Self {
+        BotError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_errors() {
+        let err = BotError::api(
+            StatusCode::FORBIDDEN,
+            r#"{"errors":[{"code":187,"message":"Status is a duplicate."}]}"#,
+        );
+        assert_eq!(err.api_code(), Some(ApiCode::DuplicateStatus));
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "twitter returned 403 Forbidden: Status is a duplicate. (187)"
+        );
+
+        let err = BotError::api(
+            StatusCode::TOO_MANY_REQUESTS,
+            r#"{"errors":[{"code":88,"message":"Rate limit exceeded"}]}"#,
+        );
+        assert_eq!(err.api_code(), Some(ApiCode::RateLimitExceeded));
+        assert!(err.is_retryable());
+
+        let err = BotError::api(
+            StatusCode::UNAUTHORIZED,
+            r#"{"errors":[{"code":89,"message":"Invalid or expired token."}]}"#,
+        );
+        assert_eq!(err.api_code(), Some(ApiCode::InvalidToken));
+        assert!(!err.is_retryable());
+
+        // a gateway in the way, not twitter itself
+        let err = BotError::api(StatusCode::BAD_GATEWAY, "<html>bad gateway</html>");
+        assert_eq!(err.api_code(), None);
+        assert!(err.is_retryable());
+        assert_eq!(err.to_string(), "twitter returned 502 Bad Gateway");
+    }
+
+    #[test]
+    fn test_media_processing_error() {
+        let error: Error = serde_json::from_str(
+            r#"{"code":324,"name":"InvalidMedia","message":"Unsupported video format"}"#,
+        )
+        .unwrap();
+        let err = BotError::MediaProcessing(Some(error));
+
+        assert_eq!(err.api_code(), Some(ApiCode::InvalidMedia));
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "media processing failed: Unsupported video format (324)"
+        );
+    }
+
+    #[test]
+    fn test_auth_keeps_transport_errors() {
+        let err = BotError::auth(Box::new(std::io::Error::from(std::io::ErrorKind::Other)));
+        assert!(matches!(err, BotError::Auth(_)));
+
+        let reqwest_err = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let err = BotError::auth(Box::new(reqwest_err));
+        assert!(matches!(err, BotError::Http(_)));
+    }
+}
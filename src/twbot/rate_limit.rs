@@ -0,0 +1,109 @@
+// twitter's per-endpoint rate limits, as reported by the x-rate-limit-*
+// headers on every response. the bot keeps the latest of each so it can
+// wait out a window instead of running into a 429.
+
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const LIMIT: &str = "x-rate-limit-limit";
+pub const REMAINING: &str = "x-rate-limit-remaining";
+pub const RESET: &str = "x-rate-limit-reset";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Requests allowed per window.
+    pub limit: u32,
+    pub remaining: u32,
+    /// Unix time the window starts over.
+    pub reset: u64,
+}
+
+impl RateLimit {
+    /// `None` unless all three headers are there.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+
+        Some(RateLimit {
+            limit: number(LIMIT)? as u32,
+            remaining: number(REMAINING)? as u32,
+            reset: number(RESET)?,
+        })
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// How long until the window starts over, give or take the second the
+    /// reset gets rounded to.
+    pub fn reset_in(&self, now: u64) -> Duration {
+        Duration::from_secs(self.reset.saturating_sub(now) + 1)
+    }
+}
+
+/// The last limits seen for each endpoint, keyed by path (e.g.
+/// `/1.1/statuses/update.json`), which is how twitter counts them.
+#[derive(Default)]
+pub struct RateLimits {
+    limits: Mutex<HashMap<String, RateLimit>>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, endpoint: &str) -> Option<RateLimit> {
+        self.limits.lock().unwrap().get(endpoint).copied()
+    }
+
+    pub fn all(&self) -> HashMap<String, RateLimit> {
+        self.limits.lock().unwrap().clone()
+    }
+
+    /// Records the limits from a response, if it had any.
+    pub fn update(&self, endpoint: &str, headers: &HeaderMap) -> Option<RateLimit> {
+        let limit = RateLimit::from_headers(headers)?;
+        self.limits
+            .lock()
+            .unwrap()
+            .insert(endpoint.to_string(), limit);
+        Some(limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(LIMIT, HeaderValue::from_static("300"));
+        headers.insert(REMAINING, HeaderValue::from_static("0"));
+        assert_eq!(RateLimit::from_headers(&headers), None);
+
+        headers.insert(RESET, HeaderValue::from_static("1318622958"));
+        let limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(
+            limit,
+            RateLimit {
+                limit: 300,
+                remaining: 0,
+                reset: 1318622958
+            }
+        );
+        assert!(limit.is_exhausted());
+        assert_eq!(limit.reset_in(1318622958 - 60), Duration::from_secs(61));
+        assert_eq!(limit.reset_in(1318622958 + 60), Duration::from_secs(1));
+
+        let limits = RateLimits::new();
+        limits.update("/1.1/statuses/update.json", &headers);
+        assert_eq!(limits.get("/1.1/statuses/update.json"), Some(limit));
+        assert_eq!(limits.get("/1.1/search/tweets.json"), None);
+    }
+}
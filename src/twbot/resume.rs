@@ -0,0 +1,146 @@
+// how far a chunked upload got, kept somewhere that outlives the process so
+// the next run can pick it up instead of starting over. a media ID is good
+// until it expires, so the state says when that is too.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::fs;
+
+type SyncError = Box<dyn std::error::Error + std::marker::Sync + std::marker::Send>;
+
+/// Uploads that expire sooner than this aren't worth resuming.
+pub const EXPIRY_MARGIN_SECS: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UploadState {
+    pub media_id: String,
+    pub total_bytes: usize,
+    /// The segment_index the next APPEND gets, i.e. how many are done.
+    pub next_segment: u16,
+    /// How much of the file's been sent.
+    pub byte_offset: usize,
+    /// Unix time the media ID stops working.
+    pub expires_at: u64,
+}
+
+impl UploadState {
+    /// Whether this is an upload of `total_bytes` that can still be
+    /// finished at `now`.
+    pub fn resumes(&self, total_bytes: usize, now: u64) -> bool {
+        self.total_bytes == total_bytes && now + EXPIRY_MARGIN_SECS < self.expires_at
+    }
+}
+
+/// Somewhere to keep one upload's progress. Uploads that shouldn't be
+/// mistaken for each other need their own store.
+#[async_trait]
+pub trait UploadStore: Send + Sync {
+    async fn load(&self) -> Result<Option<UploadState>, SyncError>;
+    async fn save(&self, state: &UploadState) -> Result<(), SyncError>;
+    /// Called once the upload's finalized, or turns out to be unusable.
+    async fn clear(&self) -> Result<(), SyncError>;
+}
+
+#[derive(Default)]
+pub struct MemoryUploadStore {
+    state: Mutex<Option<UploadState>>,
+}
+
+impl MemoryUploadStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+#[async_trait]
+impl UploadStore for MemoryUploadStore {
+    async fn load(&self) -> Result<Option<UploadState>, SyncError> {
+        Ok(self.state.lock().unwrap().clone())
+    }
+
+    async fn save(&self, state: &UploadState) -> Result<(), SyncError> {
+        *self.state.lock().unwrap() = Some(state.clone());
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), SyncError> {
+        *self.state.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+/// Keeps the state in a JSON file.
+pub struct FileUploadStore {
+    path: PathBuf,
+}
+
+impl FileUploadStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileUploadStore { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl UploadStore for FileUploadStore {
+    async fn load(&self) -> Result<Option<UploadState>, SyncError> {
+        match fs::read(&self.path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    async fn save(&self, state: &UploadState) -> Result<(), SyncError> {
+        // rename over it, so dying halfway through a write doesn't leave
+        // something that can't be read back
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(state)?).await?;
+        fs::rename(&tmp, &self.path).await?;
+
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), SyncError> {
+        match fs::remove_file(&self.path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Box::new(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_upload_store() {
+        let path =
+            std::env::temp_dir().join(format!("fox-friday-upload-{}.json", std::process::id()));
+        let store = FileUploadStore::new(&path);
+        assert_eq!(store.load().await.unwrap(), None);
+
+        let state = UploadState {
+            media_id: "710511363345354753".to_string(),
+            total_bytes: 2_500_000,
+            next_segment: 2,
+            byte_offset: 2_048_000,
+            expires_at: 1_000_000,
+        };
+        store.save(&state).await.unwrap();
+        assert_eq!(
+            FileUploadStore::new(&path).load().await.unwrap(),
+            Some(state.clone())
+        );
+
+        store.clear().await.unwrap();
+        assert_eq!(store.load().await.unwrap(), None);
+        // nothing there is fine too
+        store.clear().await.unwrap();
+
+        assert!(state.resumes(2_500_000, 1_000_000 - 61));
+        assert!(!state.resumes(2_500_000, 1_000_000 - 60));
+        assert!(!state.resumes(2_400_000, 0));
+    }
+}
@@ -0,0 +1,161 @@
+// how hard to try when twitter has a bad moment. delays double from
+// `base_delay` up to `max_delay`, with some jitter so a bunch of clients
+// that failed together don't all come back together.
+
+use super::{ApiCode, BotError};
+use rand::Rng;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Whether a call does the same thing however many times it's sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Idempotency {
+    Idempotent,
+    /// Sending it twice could do it twice (post the status twice, ...), so
+    /// it's only retried when it can't have gone through the first time.
+    NotIdempotent,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Tries in total, the first one included.
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// How much of each delay is random, from 0 (none) to 1 (anywhere
+    /// between nothing and the full delay).
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every call gets one try.
+    pub fn none() -> Self {
+        Self::default().with_attempts(1)
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    pub fn with_base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Whether to try again after `err`, with `attempt` tries made so far.
+    pub fn should_retry(&self, err: &BotError, idempotency: Idempotency, attempt: u32) -> bool {
+        if attempt >= self.attempts || !err.is_retryable() {
+            return false;
+        }
+
+        match idempotency {
+            Idempotency::Idempotent => true,
+            Idempotency::NotIdempotent => Self::not_sent(err),
+        }
+    }
+
+    /// How long to wait before retry number `retry` (counting from 0).
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.delay_with(retry, rand::thread_rng().gen())
+    }
+
+    // `random` is in [0, 1)
+    fn delay_with(&self, retry: u32, random: f64) -> Duration {
+        let backoff = self
+            .base_delay
+            .checked_mul(1 << retry.min(31))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        backoff.mul_f64(1.0 - self.jitter * random)
+    }
+
+    // failures that mean the request never got acted on: we couldn't
+    // connect, or twitter turned it away before looking at it
+    fn not_sent(err: &BotError) -> bool {
+        match err {
+            BotError::Http(err) => err.is_connect(),
+            BotError::Api { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => true,
+            _ => matches!(
+                err.api_code(),
+                Some(ApiCode::RateLimitExceeded) | Some(ApiCode::OverCapacity)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays() {
+        let policy = RetryPolicy::new()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_secs(1))
+            .with_jitter(0.5);
+
+        assert_eq!(policy.delay_with(0, 0.0), Duration::from_millis(100));
+        assert_eq!(policy.delay_with(2, 0.0), Duration::from_millis(400));
+        assert_eq!(policy.delay_with(2, 0.5), Duration::from_millis(300));
+        // capped, however long it's been going
+        assert_eq!(policy.delay_with(4, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.delay_with(40, 0.0), Duration::from_secs(1));
+
+        for retry in 0..10 {
+            let delay = policy.delay(retry);
+            assert!(delay <= Duration::from_secs(1));
+            assert!(delay >= policy.delay_with(retry, 1.0));
+        }
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new().with_attempts(3);
+        let unavailable = BotError::api(StatusCode::SERVICE_UNAVAILABLE, "");
+        let over_capacity = BotError::api(
+            StatusCode::SERVICE_UNAVAILABLE,
+            r#"{"errors":[{"code":130,"message":"Over capacity"}]}"#,
+        );
+        let duplicate = BotError::api(
+            StatusCode::FORBIDDEN,
+            r#"{"errors":[{"code":187,"message":"Status is a duplicate."}]}"#,
+        );
+
+        assert!(policy.should_retry(&unavailable, Idempotency::Idempotent, 1));
+        assert!(!policy.should_retry(&unavailable, Idempotency::Idempotent, 3));
+        assert!(!policy.should_retry(&duplicate, Idempotency::Idempotent, 1));
+
+        // a 503 might've posted it anyway, over capacity means it didn't
+        assert!(!policy.should_retry(&unavailable, Idempotency::NotIdempotent, 1));
+        assert!(policy.should_retry(&over_capacity, Idempotency::NotIdempotent, 1));
+
+        assert!(!RetryPolicy::none().should_retry(&unavailable, Idempotency::Idempotent, 1));
+    }
+}